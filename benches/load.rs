@@ -0,0 +1,74 @@
+use std::hint::black_box;
+use std::io::Write;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ip_check::{generate_test_ranges, IpRange, Looker, LookerBuilder};
+
+const ROWS: usize = 500_000;
+
+// A locationv4.csv style file of generated ranges, written once per run
+fn fixture() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ip-check-bench-{}.csv", ROWS));
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&path).expect("Could not create the benchmark file"));
+    writeln!(file, "start,end,country,country_name,region,city").unwrap();
+    for range in generate_test_ranges(ROWS, 7) {
+        writeln!(file, "{},{},{},,{},{}", range.start(), range.end(), range.country, range.region, range.city).unwrap();
+    }
+    file.flush().unwrap();
+    path
+}
+
+fn to_range(fields: [&str; 5]) -> IpRange {
+    IpRange::new(fields[0].parse().unwrap(), fields[1].parse().unwrap(), fields[2].to_string(), fields[3].to_string(), fields[4].to_string())
+}
+
+// The loop before records were reused: csv's records() allocates a StringRecord per row
+fn load_with_record_per_row(path: &PathBuf) -> Vec<IpRange> {
+    let mut rdr = csv::Reader::from_path(path).unwrap();
+    rdr.records().map(|record| {
+        let record = record.unwrap();
+        to_range([&record[0], &record[1], &record[2], &record[4], &record[5]])
+    }).collect()
+}
+
+// The loop the loader uses now, one ByteRecord for the whole file
+fn load_with_reused_record(path: &PathBuf) -> Vec<IpRange> {
+    let mut rdr = csv::Reader::from_path(path).unwrap();
+    let mut record = csv::ByteRecord::new();
+    let mut ranges = Vec::new();
+    while rdr.read_byte_record(&mut record).unwrap() {
+        let field = |index: usize| std::str::from_utf8(&record[index]).unwrap();
+        ranges.push(to_range([field(0), field(1), field(2), field(4), field(5)]));
+    }
+    ranges
+}
+
+fn record_buffer(c: &mut Criterion) {
+    let path = fixture();
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+    group.bench_function("record per row", |b| b.iter(|| black_box(load_with_record_per_row(&path))));
+    group.bench_function("reused record", |b| b.iter(|| black_box(load_with_reused_record(&path))));
+    // Everything else the loader does on top, like validation and sorting
+    group.bench_function("Looker::try_new", |b| b.iter(|| black_box(Looker::try_new(path.clone()).unwrap())));
+    group.finish();
+}
+
+// The loader sizes the range Vec from the file length. An average row length larger than any
+// file estimates 0 rows, so the Vec grows as it did before pre-sizing.
+fn pre_sizing(c: &mut Criterion) {
+    let path = fixture();
+    let mut group = c.benchmark_group("pre-sizing");
+    group.sample_size(10);
+    group.bench_function("estimated from file size", |b| {
+        b.iter(|| black_box(LookerBuilder::new().file_path(path.clone()).try_build().unwrap()))
+    });
+    group.bench_function("growing from empty", |b| {
+        b.iter(|| black_box(LookerBuilder::new().file_path(path.clone()).average_row_length(u64::MAX).try_build().unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, record_buffer, pre_sizing);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use std::hint::black_box;
+use std::net::Ipv4Addr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ip_check::{generate_test_ranges, IpLookup, RangeIndex};
+
+// About the size of locationv4.csv
+const RANGES: usize = 3_000_000;
+
+// Spread over the whole address space, hits and misses alike
+fn query_ips() -> Vec<Ipv4Addr> {
+    (0..10_000u32).map(|i| Ipv4Addr::from(i.wrapping_mul(2_654_435_761))).collect()
+}
+
+fn prefix_index_vs_binary_search(c: &mut Criterion) {
+    let plain: RangeIndex = generate_test_ranges(RANGES, 1).into_iter().collect();
+    let indexed = plain.clone().with_prefix_index();
+    let ips = query_ips();
+
+    let mut group = c.benchmark_group("look_up_ipv4");
+    group.bench_function("binary search", |b| b.iter(|| {
+        for ip in &ips {
+            black_box(plain.look_up_ipv4(ip));
+        }
+    }));
+    group.bench_function("prefix index", |b| b.iter(|| {
+        for ip in &ips {
+            black_box(indexed.look_up_ipv4(ip));
+        }
+    }));
+    group.finish();
+}
+
+fn sorted_batch_vs_binary_search(c: &mut Criterion) {
+    let index: RangeIndex = generate_test_ranges(RANGES, 1).into_iter().collect();
+    for (name, count) in [("dense", 1_000_000u32), ("sparse", 1_000)] {
+        let mut ips: Vec<Ipv4Addr> = (0..count).map(|i| Ipv4Addr::from(i.wrapping_mul(2_654_435_761))).collect();
+        ips.sort();
+
+        let mut group = c.benchmark_group(format!("sorted batch, {}", name));
+        group.sample_size(20);
+        group.bench_function("binary search per IP", |b| b.iter(|| {
+            black_box(ips.iter().map(|ip| index.look_up_u32(u32::from(*ip))).collect::<Vec<_>>())
+        }));
+        group.bench_function("look_up_sorted_batch", |b| b.iter(|| black_box(index.look_up_sorted_batch(&ips))));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, prefix_index_vs_binary_search, sorted_batch_vs_binary_search);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+use std::io::{self, Read, Write};
+
+use crate::ip_lookup::IpRange;
+
+// Compact binary database, for preprocessing a csv once and loading it quickly afterwards.
+// After the magic and version every range is its start and end as little-endian u32, followed
+// by the country, region and city, each as a little-endian u16 length and that many bytes of
+// UTF-8. Ranges are stored in the order they were written, the reader sorts them on load.
+// Only the bounds and the location are kept.
+//
+// The network order layout, for dumps produced by other tools, has no magic or version and
+// is the same records with every u32 and u16 big-endian instead.
+pub(crate) const MAGIC: &[u8; 4] = b"IPCK";
+pub(crate) const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ByteOrder {
+    // The crate's own format
+    Little,
+    // Network order
+    Big,
+}
+
+pub(crate) fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])
+}
+
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an ip-check binary database"));
+    }
+    if header[4] != VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported binary database version {}", header[4])));
+    }
+    Ok(())
+}
+
+pub(crate) fn write_range<W: Write>(writer: &mut W, range: &IpRange) -> io::Result<()> {
+    writer.write_all(&range.start().to_le_bytes())?;
+    writer.write_all(&range.end().to_le_bytes())?;
+    for text in [&range.country, &range.region, &range.city] {
+        let length = u16::try_from(text.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Location field of {} bytes is too long", text.len())))?;
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+// None at a clean end of the data, an error if it ends in the middle of a range
+pub(crate) fn read_range<R: Read>(reader: &mut R, order: ByteOrder) -> io::Result<Option<IpRange>> {
+    let mut start = [0u8; 4];
+    match reader.read(&mut start[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut start[1..])?,
+    }
+    let start = match order {
+        ByteOrder::Little => u32::from_le_bytes(start),
+        ByteOrder::Big => u32::from_be_bytes(start),
+    };
+    let end = read_u32(reader, order)?;
+    let country = read_text(reader, order)?;
+    let region = read_text(reader, order)?;
+    let city = read_text(reader, order)?;
+    Ok(Some(IpRange::new(start, end, country, region, city)))
+}
+
+fn read_u32<R: Read>(reader: &mut R, order: ByteOrder) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(match order {
+        ByteOrder::Little => u32::from_le_bytes(bytes),
+        ByteOrder::Big => u32::from_be_bytes(bytes),
+    })
+}
+
+fn read_text<R: Read>(reader: &mut R, order: ByteOrder) -> io::Result<String> {
+    let mut length = [0u8; 2];
+    reader.read_exact(&mut length)?;
+    let length = match order {
+        ByteOrder::Little => u16::from_le_bytes(length),
+        ByteOrder::Big => u16::from_be_bytes(length),
+    };
+    let mut text = vec![0u8; length as usize];
+    reader.read_exact(&mut text)?;
+    String::from_utf8(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,56 @@
+use std::net::Ipv4Addr;
+
+// What kind of address an IP is, from the IANA special-purpose registry. Anything not in one
+// of the special blocks is Public.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpClass {
+    Public,
+    // 10.0.0.0/8, 172.16.0.0/12 and 192.168.0.0/16
+    Private,
+    // 100.64.0.0/10, carrier-grade NAT
+    Shared,
+    Loopback,
+    LinkLocal,
+    Multicast,
+    Broadcast,
+    Unspecified,
+    Documentation,
+    // 198.18.0.0/15
+    Benchmarking,
+    // 240.0.0.0/4 except the broadcast address
+    Reserved,
+}
+
+impl IpClass {
+    // Every class but Public, addresses that no geo database can place
+    pub fn is_special(&self) -> bool {
+        *self != IpClass::Public
+    }
+}
+
+pub fn classify(ip: &Ipv4Addr) -> IpClass {
+    let octets = ip.octets();
+    if ip.is_unspecified() {
+        IpClass::Unspecified
+    } else if ip.is_broadcast() {
+        IpClass::Broadcast
+    } else if ip.is_loopback() {
+        IpClass::Loopback
+    } else if ip.is_private() {
+        IpClass::Private
+    } else if octets[0] == 100 && (octets[1] & 0xC0) == 64 {
+        IpClass::Shared
+    } else if ip.is_link_local() {
+        IpClass::LinkLocal
+    } else if ip.is_multicast() {
+        IpClass::Multicast
+    } else if ip.is_documentation() {
+        IpClass::Documentation
+    } else if octets[0] == 198 && (octets[1] & 0xFE) == 18 {
+        IpClass::Benchmarking
+    } else if octets[0] >= 240 {
+        IpClass::Reserved
+    } else {
+        IpClass::Public
+    }
+}
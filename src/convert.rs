@@ -0,0 +1,97 @@
+use std::net::{AddrParseError, Ipv4Addr};
+use std::str::FromStr;
+
+// Conversions between the dotted-quad, decimal and hex forms of an IPv4 address.
+// Decimal and hex are the address read as a big-endian u32, like the start and end of an IpRange.
+
+pub fn decimal_to_ip(decimal: u32) -> Ipv4Addr {
+    Ipv4Addr::from(decimal)
+}
+
+pub fn ip_to_decimal(ip: &Ipv4Addr) -> u32 {
+    u32::from(*ip)
+}
+
+// The four bytes of an address in network order, as read from a packet, packed into its decimal form
+pub fn from_octets(octets: [u8; 4]) -> u32 {
+    (octets[0] as u32) << 24
+        | (octets[1] as u32) << 16
+        | (octets[2] as u32) << 8
+        | octets[3] as u32
+}
+
+// Accepts upper and lower case digits, an optional 0x prefix and leading zeros,
+// so "0A000001", "0xa000001" and "a000001" are all 10.0.0.1
+pub fn hex_to_ip(hex: &str) -> Result<Ipv4Addr, String> {
+    let trimmed = hex.trim();
+    let digits = trimmed.strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    let digits = digits.trim_start_matches('0');
+
+    if digits.len() > 8 {
+        return Err(format!("Hex IP '{}' does not fit in 32 bits", hex));
+    }
+    if digits.is_empty() {
+        // Only zeros, but an empty string or a bare prefix is not an address
+        return match trimmed.ends_with('0') {
+            true => Ok(Ipv4Addr::UNSPECIFIED),
+            false => Err(format!("Invalid hex IP '{}'", hex)),
+        };
+    }
+    match u32::from_str_radix(digits, 16) {
+        Ok(decimal) if digits.bytes().all(|b| b.is_ascii_hexdigit()) => Ok(decimal_to_ip(decimal)),
+        _ => Err(format!("Invalid hex IP '{}'", hex)),
+    }
+}
+
+// Always 8 lowercase digits without a prefix, 10.0.0.1 is "0a000001"
+pub fn ip_to_hex(ip: &Ipv4Addr) -> String {
+    format!("{:08x}", ip_to_decimal(ip))
+}
+
+// Dotted-quad parsing without a lookup, surrounding whitespace is ignored
+pub fn parse_ipv4(s: &str) -> Result<Ipv4Addr, AddrParseError> {
+    Ipv4Addr::from_str(s.trim())
+}
+
+pub fn is_valid_ipv4(s: &str) -> bool {
+    parse_ipv4(s).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips_in_either_case_with_leading_zeros() {
+        for ip in [Ipv4Addr::new(0, 0, 0, 0), Ipv4Addr::new(0, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(171, 205, 239, 18), Ipv4Addr::new(255, 255, 255, 255)] {
+            let hex = ip_to_hex(&ip);
+            assert_eq!(hex.len(), 8);
+            assert_eq!(hex_to_ip(&hex), Ok(ip));
+            assert_eq!(hex_to_ip(&hex.to_uppercase()), Ok(ip));
+            assert_eq!(hex_to_ip(&format!("0x{}", hex)), Ok(ip));
+            assert_eq!(decimal_to_ip(ip_to_decimal(&ip)), ip);
+        }
+        assert_eq!(ip_to_hex(&Ipv4Addr::new(171, 205, 239, 18)), "abcdef12");
+
+        let ten = Ipv4Addr::new(10, 0, 0, 1);
+        for hex in ["0A000001", "0a000001", "0xa000001", "0XA000001", "a000001", "000000000a000001", " 0a000001 "] {
+            assert_eq!(hex_to_ip(hex), Ok(ten), "{}", hex);
+        }
+        assert_eq!(hex_to_ip("0"), Ok(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(hex_to_ip("0x00000000"), Ok(Ipv4Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn invalid_hex_and_dotted_quads_are_errors() {
+        for hex in ["", "0x", "0X", "100000000", "0a00000g", "-1", "0x-1", "+a000001"] {
+            assert!(hex_to_ip(hex).is_err(), "{}", hex);
+        }
+        // Leading zeros in a dotted quad could be read as octal, so they are rejected
+        assert!(parse_ipv4("010.0.0.1").is_err());
+        assert_eq!(parse_ipv4(" 10.0.0.1 "), Ok(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(!is_valid_ipv4("256.0.0.1"));
+        assert_eq!(from_octets([10, 0, 0, 1]), 0x0a00_0001);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::ip_lookup::{IpRange, RangeIndex};
+
+pub const CIP_COUNTRY_LENGTH: usize = 64;
+pub const CIP_TEXT_LENGTH: usize = 128;
+
+// A lookup result laid out for C. The strings are stored inline as null-terminated UTF-8,
+// cut at a character boundary when they don't fit, so the struct owns everything it holds
+// and there is nothing to free on either side.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CIpRange {
+    pub start: u32,
+    pub end: u32,
+    pub country: [u8; CIP_COUNTRY_LENGTH],
+    pub region: [u8; CIP_TEXT_LENGTH],
+    pub city: [u8; CIP_TEXT_LENGTH],
+}
+
+impl From<&IpRange> for CIpRange {
+    fn from(range: &IpRange) -> Self {
+        CIpRange {
+            start: range.start(),
+            end: range.end(),
+            country: null_terminated(&range.country),
+            region: null_terminated(&range.region),
+            city: null_terminated(&range.city),
+        }
+    }
+}
+
+impl RangeIndex {
+    /// Writes the range containing ip into out and returns true, or returns false and leaves
+    /// out untouched on a miss or when out is null.
+    ///
+    /// # Safety
+    /// out must be null or point to memory valid for writing a CIpRange.
+    pub unsafe fn look_up_c(&self, ip: u32, out: *mut CIpRange) -> bool {
+        if out.is_null() {
+            return false;
+        }
+        match self.look_up_u32(ip) {
+            Some(range) => {
+                out.write(CIpRange::from(range));
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+// Leaves room for the terminating null, which is always there
+fn null_terminated<const N: usize>(value: &str) -> [u8; N] {
+    let mut buffer = [0u8; N];
+    let mut length = value.len().min(N - 1);
+    while !value.is_char_boundary(length) {
+        length -= 1;
+    }
+    buffer[..length].copy_from_slice(&value.as_bytes()[..length]);
+    buffer
+}
@@ -1,164 +1,3340 @@
 pub mod ip_lookup {
 
-    use std::net::Ipv4Addr;
-    use std::path::PathBuf;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::{Path, PathBuf};
     use std::str::FromStr;
     use std::cmp::Ordering;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
     use std::error::Error;
-    use csv::Reader;
+    use std::fmt;
+    use std::ops::{Deref, Range};
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use std::borrow::Cow;
+    use csv::{ByteRecord, ReaderBuilder, StringRecord};
+    use crate::countries::normalize_country;
+    use crate::convert::from_octets;
+    use crate::classify::{classify, IpClass};
 
 
     #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize))]
     pub struct IpRange {
         start: u32,
         end: u32,
         pub country: String,
+        #[cfg_attr(feature = "serde", serde(default))]
         pub region: String,
+        #[cfg_attr(feature = "serde", serde(default))]
         pub city: String,
+        // Served from many locations, so the location is only one of them. Read from
+        // LookerBuilder::anycast_column, false without one.
+        #[cfg_attr(feature = "serde", serde(default))]
+        anycast: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        source: Option<Arc<str>>,
+        // Ids of the country, region and city in the symbol table, see RangeIndex::with_symbols
+        #[cfg_attr(feature = "serde", serde(skip))]
+        symbol_ids: Option<[u32; 3]>,
+        // Made up by RangeIndex::look_up_or_guess rather than read from the database
+        #[cfg_attr(feature = "serde", serde(skip))]
+        synthetic: bool,
+        // None unless the range has any of the optional data, see RangeExtras
+        #[cfg_attr(feature = "serde", serde(flatten, deserialize_with = "deserialize_extras"))]
+        extras: Option<Box<RangeExtras>>,
     }
 
+    // The optional columns of a range. Most databases have none of them, so they live behind
+    // one pointer in IpRange and a range without any costs 8 bytes instead of a few hundred.
+    #[derive(Debug, Clone, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+    struct RangeExtras {
+        // Organization or netname owning the range, when the database has one
+        #[cfg_attr(feature = "serde", serde(default))]
+        org: Option<String>,
+        // Full country name, when the database has a column for it next to the code
+        #[cfg_attr(feature = "serde", serde(default))]
+        country_name: Option<String>,
+        // ISO 3166-2 code of the region, like US-CA, when mapped with LookerBuilder::region_code_column
+        #[cfg_attr(feature = "serde", serde(default))]
+        region_code: Option<String>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        latitude: Option<f64>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        longitude: Option<f64>,
+        // The csv row, only with LookerBuilder::keep_raw
+        #[cfg_attr(feature = "serde", serde(skip))]
+        raw: Option<StringRecord>,
+        // Every weighted location of a probabilistic database, highest weight first.
+        // Empty when the range only has its primary location.
+        #[cfg_attr(feature = "serde", serde(skip))]
+        candidates: Vec<(Location, f32)>,
+        // Extra columns mapped with LookerBuilder::attribute_column
+        #[cfg_attr(feature = "serde", serde(skip))]
+        attributes: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct Location {
+        pub country: String,
+        pub region: String,
+        pub city: String,
+    }
+
+    // Caller-owned output of RangeIndex::look_up_into. Every lookup overwrites the strings in
+    // place, so once they have grown to the longest values seen a lookup allocates nothing.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct IpRangeBuf {
+        pub start: u32,
+        pub end: u32,
+        pub country: String,
+        pub region: String,
+        pub city: String,
+    }
+
+    impl IpRangeBuf {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        // Empties the strings but keeps their capacity
+        pub fn clear(&mut self) {
+            self.start = 0;
+            self.end = 0;
+            self.country.clear();
+            self.region.clear();
+            self.city.clear();
+        }
+    }
+
+    // IP ranges ordered by start (then end), which the binary search in every lookup relies on.
+    // Can only be constructed through from_unsorted, which sorts, or new, which checks the order.
+    // The ranges are never changed in place, so clones share them.
+    #[derive(Debug, Clone, Default)]
+    pub struct SortedRanges(Arc<[IpRange]>);
+
+    // Pure range index with no knowledge of csv or files: built from IP ranges and
+    // searched by address. Looker loads one from a file and derefs to it.
+    #[derive(Debug, Clone, Default)]
+    pub struct RangeIndex {
+        pub ip_ranges: SortedRanges,
+        prefix_index: Option<Arc<PrefixIndex>>,
+        // The largest end among the ranges up to and including each index, so a search for
+        // overlapping ranges knows when nothing further back can contain an address
+        max_end: Arc<[u32]>,
+        // Every distinct country, region and city, indexed by symbol id, when enabled with with_symbols
+        symbols: Option<Arc<[Box<str>]>>,
+    }
+
+    // Cloning a Looker is cheap, clones share the loaded ranges. The ranges live in a RangeIndex
+    // unless the Looker was made with from_store around another RangeStore. Loading, reloading
+    // and the RangeIndex queries are only there for the default store, a custom store gets
+    // IpLookup and whatever its own methods are through Deref.
+    #[derive(Debug, Clone)]
+    pub struct Looker<S = RangeIndex> {
+        pub file_path: PathBuf,
+        index: S,
+        options: LoadOptions,
+        file_state: Option<FileState>,
+        format: SourceFormat,
+        load_stats: LoadStats,
+        skip_reserved: bool,
+        // Edition of the database, see LookerBuilder::version
+        version: Option<Arc<str>>,
+        // SHA-256 of the file, when it was loaded with from_path_verified
+        #[cfg(feature = "checksum")]
+        checksum: Option<String>,
+    }
+
+    // Startup only reads the start and end of every row and remembers where the row is in the
+    // file. A row is parsed into an IpRange the first time a lookup lands in it and cached from
+    // then on. Opens much faster than a Looker and uses less memory when only a few IPs are
+    // looked up, like a CLI answering one query, but every new row costs a file read, so a
+    // Looker is faster for batches. Reads the default locationv4.csv layout.
     #[derive(Debug)]
-    pub struct Looker {
+    pub struct LazyLooker {
         pub file_path: PathBuf,
-        pub ip_ranges: Vec<IpRange>,
+        // Sorted by start
+        rows: Vec<LazyRow>,
+        file: Mutex<std::fs::File>,
+        // Parsed rows by their index in rows
+        cache: Mutex<HashMap<usize, IpRange>>,
+    }
+
+    // Bounds of a row and where its bytes are in the file
+    #[derive(Debug, Clone, Copy)]
+    struct LazyRow {
+        start: u32,
+        end: u32,
+        offset: u64,
+        len: u64,
+    }
+
+    // What happened to the rows of the csv while it was loaded
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct LoadStats {
+        pub rows_read: usize,
+        // Ranges larger than max_range_size that were left out
+        pub coarse_dropped: usize,
+        // Ranges larger than max_range_size that only kept their country
+        pub coarse_downgraded: usize,
+        // Exact repeats of the previous row dropped by dedup
+        pub duplicates_removed: usize,
+        // Rows entirely outside the interval given to only_within
+        pub out_of_scope: usize,
+        // Rows whose end is before their start, skipped
+        pub reversed: usize,
+        // Ranges in the Looker once loading finished
+        pub rows_kept: usize,
+    }
+
+    // What the source file looked like when it was last loaded, to detect changes to it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct FileState {
+        modified: Option<SystemTime>,
+        len: u64,
+    }
+
+    // What changed going from one database to another. A range with the same bounds in both
+    // but a different country, region or city is listed in changed as (old, new).
+    #[derive(Debug, Clone, Default)]
+    pub struct DatabaseDiff {
+        pub added: Vec<IpRange>,
+        pub removed: Vec<IpRange>,
+        pub changed: Vec<(IpRange, IpRange)>,
+    }
+
+    // Inclusive intervals of addresses covered by only one of two databases, see
+    // RangeIndex::coverage_diff. Sorted, disjoint and never adjacent.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CoverageDiff {
+        pub only_in_self: Vec<(u32, u32)>,
+        pub only_in_other: Vec<(u32, u32)>,
+    }
+
+    // The locations found in a CIDR block and the fraction of its addresses each one covers,
+    // see RangeIndex::summarize_block. Locations are ordered by fraction, largest first.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BlockSummary {
+        pub start: u32,
+        pub end: u32,
+        pub locations: Vec<(Location, f64)>,
+        // Fraction of the block not covered by any range
+        pub unassigned: f64,
+    }
+
+    // Byte ranges of each column in a fixed-width file. Values are trimmed of their padding.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FixedWidthSchema {
+        pub start: Range<usize>,
+        pub end: Range<usize>,
+        pub country: Range<usize>,
+        pub region: Range<usize>,
+        pub city: Range<usize>,
+    }
+
+    // Readiness summary of a loaded database, see Looker::health
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Health {
+        pub row_count: usize,
+        pub non_empty: bool,
+        pub non_overlapping: bool,
+        pub covered_addresses: u64,
+        pub coverage: f64,
+    }
+
+    // For every /16 block, the window of range indices that can contain an address in it.
+    // Narrows the binary search down to a handful of ranges at the cost of 512KB.
+    #[derive(Debug, Clone)]
+    struct PrefixIndex {
+        windows: Vec<(u32, u32)>,
+    }
+
+    // How Looker::reload reads the file_path
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum SourceFormat {
+        Csv,
+        // Written by LookerBuilder::write_binary, see Looker::from_binary
+        Binary,
+        // See Looker::from_network_order_binary
+        NetworkOrderBinary,
+        // See Looker::from_fixed_width
+        FixedWidth(FixedWidthSchema),
+    }
+
+    #[derive(Debug, Default)]
+    pub struct LookerBuilder {
+        file_path: Option<PathBuf>,
+        options: LoadOptions,
+        reject_empty: bool,
+        with_trie: bool,
+        intern_symbols: bool,
+        skip_reserved: bool,
+        version: Option<Arc<str>>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum IpFamily {
+        V4,
+        V6,
+    }
+
+    // How specific the location of a range is, see IpRange::precision
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum Precision {
+        Unknown,
+        Country,
+        Region,
+        City,
+    }
+
+    // Which location columns the loader turns into strings, the others are left empty
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum FieldSet {
+        #[default]
+        All,
+        CountryOnly,
+    }
+
+    // How the end column of a csv is meant. Inclusive, the default, is the last address of the
+    // range, Exclusive the first address after it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum EndBound {
+        #[default]
+        Inclusive,
+        Exclusive,
+    }
+
+    // Output format of RangeIndex::write_country_report, Json needs the serde feature.
+    // Non-exhaustive since the variants depend on the features enabled anywhere in the build.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ReportFormat {
+        Csv,
+        #[cfg(feature = "serde")]
+        Json,
+    }
+
+    // Where the bounds of a range are read from in a csv row
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum BoundsColumns {
+        StartEnd(usize, usize),
+        // A start and the number of addresses from it
+        StartCount(usize, usize),
+        Cidr(usize),
+        Dashed(usize),
+    }
+
+    // Everything that affects how rows in the csv are turned into IP ranges
+    #[derive(Debug, Clone)]
+    struct LoadOptions {
+        allowed_countries: Option<Vec<String>>,
+        keep_raw: bool,
+        normalize_countries: bool,
+        fields: FieldSet,
+        lossy_utf8: bool,
+        trim_fields: bool,
+        has_headers: bool,
+        delimiter: u8,
+        comment: Option<u8>,
+        bounds: BoundsColumns,
+        end_bound: EndBound,
+        // Country, region and city
+        location_columns: (usize, usize, usize),
+        // Latitude and longitude
+        coordinate_columns: Option<(usize, usize)>,
+        // Weight of the primary location, followed by alternative locations
+        candidate_columns: Option<usize>,
+        // Number of addresses above which a range is too coarse for its region and city
+        max_range_size: Option<u64>,
+        keep_coarse_countries: bool,
+        // Number of data rows after which loading fails
+        max_rows: Option<usize>,
+        // Bytes per row assumed when pre-sizing the range table from the file size
+        average_row_length: u64,
+        org_column: Option<usize>,
+        country_name_column: Option<usize>,
+        region_code_column: Option<usize>,
+        anycast_column: Option<usize>,
+        // Attribute name and the column it is read from
+        attribute_columns: Vec<(String, usize)>,
+        // Country, region and city that must not be empty
+        required_fields: (bool, bool, bool),
+        // Stored instead of an empty country, region and city
+        empty_placeholders: (Option<String>, Option<String>, Option<String>),
+        // Inclusive interval of the address space to load, rows outside it are skipped
+        only_within: Option<(u32, u32)>,
+        dedup: bool,
+        source: Option<Arc<str>>,
+    }
+
+    impl LoadOptions {
+        // Number of columns a row needs for every column these options read
+        fn required_columns(&self) -> usize {
+            let bounds = match self.bounds {
+                BoundsColumns::StartEnd(start, end) | BoundsColumns::StartCount(start, end) => start.max(end),
+                BoundsColumns::Cidr(column) | BoundsColumns::Dashed(column) => column,
+            };
+            let (country, region, city) = self.location_columns;
+            let mut last = bounds.max(country);
+            if self.fields == FieldSet::All {
+                last = last.max(region).max(city);
+            }
+            if let Some((latitude, longitude)) = self.coordinate_columns {
+                last = last.max(latitude).max(longitude);
+            }
+            for (_, column) in &self.attribute_columns {
+                last = last.max(*column);
+            }
+            last.max(self.candidate_columns.unwrap_or(0)).max(self.org_column.unwrap_or(0)).max(self.country_name_column.unwrap_or(0)).max(self.region_code_column.unwrap_or(0)).max(self.anycast_column.unwrap_or(0)) + 1
+        }
+    }
+
+    impl Default for LoadOptions {
+        fn default() -> Self {
+            LoadOptions {
+                allowed_countries: None,
+                keep_raw: false,
+                normalize_countries: false,
+                fields: FieldSet::All,
+                lossy_utf8: false,
+                trim_fields: false,
+                has_headers: true,
+                delimiter: b',',
+                comment: None,
+                bounds: BoundsColumns::StartEnd(0, 1),
+                end_bound: EndBound::Inclusive,
+                location_columns: (2, 4, 5),
+                coordinate_columns: None,
+                candidate_columns: None,
+                max_range_size: None,
+                keep_coarse_countries: false,
+                max_rows: None,
+                average_row_length: AVERAGE_ROW_LENGTH,
+                org_column: None,
+                country_name_column: None,
+                region_code_column: None,
+                anycast_column: None,
+                attribute_columns: Vec::new(),
+                required_fields: (false, false, false),
+                empty_placeholders: (None, None, None),
+                only_within: None,
+                dedup: false,
+                source: None,
+            }
+        }
+    }
+
+    // Non-exhaustive like ReportFormat, some variants only exist with the zip, serde and
+    // checksum features
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum LookerError {
+        MissingFilePath,
+        InvalidFilePath(PathBuf),
+        Csv(csv::Error),
+        // A field in the csv that could not be parsed, row is the line number in the file
+        Parse { row: u64, column: &'static str, value: String, expected: &'static str },
+        EmptyDatabase(PathBuf),
+        // A required location field was empty or only whitespace
+        EmptyField { row: u64, column: &'static str },
+        // A csv row with fewer columns than the options read from, or a different number than the rows before it
+        WrongColumnCount { row: u64, found: usize, expected: usize },
+        Unsorted { index: usize },
+        InvalidRange { index: usize, start: u32, end: u32 },
+        // The file has more data rows than LookerBuilder::max_rows allows, row is the line where loading stopped
+        TooManyRows { max: usize, row: u64 },
+        Io(std::io::Error),
+        #[cfg(feature = "zip")]
+        Zip(zip::result::ZipError),
+        // The archive has no csv entry, or several and none was named
+        #[cfg(feature = "zip")]
+        ZipEntry { csv_entries: Vec<String> },
+        #[cfg(feature = "serde")]
+        Json { line: usize, error: serde_json::Error },
+        // The file's SHA-256 isn't the expected one, both in lowercase hex
+        #[cfg(feature = "checksum")]
+        ChecksumMismatch { expected: String, actual: String },
+    }
+
+    impl fmt::Display for LookerError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LookerError::MissingFilePath => write!(f, "No IP CSV file path was given"),
+                LookerError::InvalidFilePath(path) => write!(f, "Invalid file path: {}", path.display()),
+                LookerError::Csv(e) => write!(f, "Could not read IP CSV file: {}", e),
+                LookerError::Parse { row, column, value, expected } => write!(f, "row {}, column '{}': could not parse '{}' as {}", row, column, value, expected),
+                LookerError::EmptyDatabase(path) => write!(f, "No IP ranges were loaded from {}", path.display()),
+                LookerError::EmptyField { row, column } => write!(f, "row {}, column '{}' is empty", row, column),
+                LookerError::WrongColumnCount { row, found, expected } => write!(f, "row {} has {} columns, expected {}", row, found, expected),
+                LookerError::Unsorted { index } => write!(f, "IP range at index {} starts before the previous one", index),
+                LookerError::InvalidRange { index, start, end } => write!(f, "IP range at index {} ends ({}) before it starts ({})", index, end, start),
+                LookerError::TooManyRows { max, row } => write!(f, "More than {} rows, stopped loading at row {}", max, row),
+                LookerError::Io(e) => write!(f, "Could not read IP ranges: {}", e),
+                #[cfg(feature = "zip")]
+                LookerError::Zip(e) => write!(f, "Could not read zip archive: {}", e),
+                #[cfg(feature = "zip")]
+                LookerError::ZipEntry { csv_entries } => write!(f, "Expected a single csv in the zip archive, found {}: {:?}", csv_entries.len(), csv_entries),
+                #[cfg(feature = "serde")]
+                LookerError::Json { line, error } => write!(f, "Invalid IP range on line {}: {}", line, error),
+                #[cfg(feature = "checksum")]
+                LookerError::ChecksumMismatch { expected, actual } => write!(f, "Checksum mismatch: expected SHA-256 {}, file has {}", expected, actual),
+            }
+        }
+    }
+
+    impl Error for LookerError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                LookerError::Csv(e) => Some(e),
+                LookerError::Io(e) => Some(e),
+                #[cfg(feature = "zip")]
+                LookerError::Zip(e) => Some(e),
+                #[cfg(feature = "serde")]
+                LookerError::Json { error, .. } => Some(error),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<csv::Error> for LookerError {
+        fn from(e: csv::Error) -> Self {
+            LookerError::Csv(e)
+        }
+    }
+
+    impl From<std::io::Error> for LookerError {
+        fn from(e: std::io::Error) -> Self {
+            LookerError::Io(e)
+        }
+    }
+
+    // Why look_up_detailed found no range
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LookupError {
+        InvalidIp(String),
+        // A special-purpose address, only returned when the Looker was built with skip_reserved
+        Reserved(IpClass),
+        NotFound,
+    }
+
+    impl fmt::Display for LookupError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LookupError::InvalidIp(ip) => write!(f, "Invalid IP address: '{}'", ip),
+                LookupError::Reserved(class) => write!(f, "{:?} address, not looked up", class),
+                LookupError::NotFound => write!(f, "IP not found in any range"),
+            }
+        }
+    }
+
+    impl Error for LookupError {}
+
+    // Everything a lookup can end in, see Looker::resolve
+    #[derive(Debug, Clone)]
+    pub enum Resolution {
+        // A special-purpose address, only returned when the Looker was built with skip_reserved
+        Reserved(IpClass),
+        NotFound,
+        // Boxed so the other outcomes stay small
+        Found { range: Box<IpRange>, precision: Precision },
+    }
+
+    // How a claimed country compares to the database, see RangeIndex::verify_location
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LocationMatch {
+        Match,
+        // The country the database has for the IP
+        Mismatch { actual: String },
+        // The IP isn't in any range
+        Unknown,
+    }
+
+    // A batch had more addresses than its budget allowed, nothing was looked up
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct BudgetExceeded {
+        pub requested: usize,
+        pub max: usize,
+    }
+
+    impl fmt::Display for BudgetExceeded {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Batch of {} IPs exceeds the budget of {}", self.requested, self.max)
+        }
+    }
+
+    impl Error for BudgetExceeded {}
+
+    pub trait IpLookup {
+        fn look_up(&self, ip: &str) -> Option<IpRange>;
+        fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange>;
+    }
+
+    // Where a Looker keeps its ranges, for trying other layouts than the sorted Vec of a
+    // RangeIndex. search returns the index of the range containing ip, which get then has to
+    // accept, and None on a miss.
+    pub trait RangeStore {
+        fn search(&self, ip: u32) -> Option<usize>;
+        fn get(&self, index: usize) -> &IpRange;
+    }
+
+    impl IpRange {
+        pub fn new(start: u32, end: u32, country: String, region: String, city: String) -> Self {
+            IpRange { start, end, country, region, city, anycast: false, symbol_ids: None, synthetic: false, source: None, extras: None }
+        }
+
+        fn extras_mut(&mut self) -> &mut RangeExtras {
+            self.extras.get_or_insert_with(Default::default)
+        }
+
+        // Sets the weighted locations of the range. The highest weighted one becomes the
+        // country, region and city of the range.
+        pub fn with_candidates(mut self, mut candidates: Vec<(Location, f32)>) -> Self {
+            candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+            if let Some((primary, _)) = candidates.first() {
+                self.country = primary.country.clone();
+                self.region = primary.region.clone();
+                self.city = primary.city.clone();
+            }
+            self.extras_mut().candidates = candidates;
+            self
+        }
+
+        pub fn location(&self) -> Location {
+            Location {
+                country: self.country.clone(),
+                region: self.region.clone(),
+                city: self.city.clone(),
+            }
+        }
+
+        pub fn with_coordinates(mut self, latitude: f64, longitude: f64) -> Self {
+            let extras = self.extras_mut();
+            extras.latitude = Some(latitude);
+            extras.longitude = Some(longitude);
+            self
+        }
+
+        pub fn with_org(mut self, org: &str) -> Self {
+            self.extras_mut().org = Some(org.to_string());
+            self
+        }
+
+        // Organization or netname owning the range, when the database has one
+        pub fn org(&self) -> Option<&str> {
+            self.extras.as_ref()?.org.as_deref()
+        }
+
+        pub fn with_country_name(mut self, country_name: &str) -> Self {
+            self.extras_mut().country_name = Some(country_name.to_string());
+            self
+        }
+
+        // Full country name, when the database has a column for it next to the code
+        pub fn country_name(&self) -> Option<&str> {
+            self.extras.as_ref()?.country_name.as_deref()
+        }
+
+        pub fn with_attribute(mut self, key: &str, value: &str) -> Self {
+            self.extras_mut().attributes.insert(key.to_string(), value.to_string());
+            self
+        }
+
+        pub fn attribute(&self, key: &str) -> Option<&str> {
+            self.extras.as_ref()?.attributes.get(key).map(String::as_str)
+        }
+
+        pub fn with_region_code(mut self, region_code: &str) -> Self {
+            self.extras_mut().region_code = Some(region_code.to_string());
+            self
+        }
+
+        pub fn region_code(&self) -> Option<&str> {
+            self.extras.as_ref()?.region_code.as_deref()
+        }
+
+        pub fn with_anycast(mut self, anycast: bool) -> Self {
+            self.anycast = anycast;
+            self
+        }
+
+        pub fn is_anycast(&self) -> bool {
+            self.anycast
+        }
+
+        // The region field, named to pair with region_code
+        pub fn region_name(&self) -> &str {
+            &self.region
+        }
+
+        // Symbol ids of the location, only set for ranges in a RangeIndex built with_symbols.
+        // Look the string up with RangeIndex::resolve_symbol.
+        pub fn country_id(&self) -> Option<u32> {
+            self.symbol_ids.map(|ids| ids[0])
+        }
+
+        pub fn region_id(&self) -> Option<u32> {
+            self.symbol_ids.map(|ids| ids[1])
+        }
+
+        pub fn city_id(&self) -> Option<u32> {
+            self.symbol_ids.map(|ids| ids[2])
+        }
+
+        // True for the ranges look_up_or_guess makes up on a miss, false for every range
+        // loaded from a database
+        pub fn is_synthetic(&self) -> bool {
+            self.synthetic
+        }
+
+        pub fn with_source(mut self, source: &str) -> Self {
+            self.source = Some(source.into());
+            self
+        }
+
+        // Name of the database this range was loaded from, if one was given with
+        // LookerBuilder::source. Kept through Looker::merge.
+        pub fn source(&self) -> Option<&str> {
+            self.source.as_deref()
+        }
+
+        // Latitude and longitude, when the database has them
+        pub fn coordinates(&self) -> Option<(f64, f64)> {
+            let extras = self.extras.as_ref()?;
+            extras.latitude.zip(extras.longitude)
+        }
+
+        pub fn start(&self) -> u32 {
+            self.start
+        }
+
+        pub fn end(&self) -> u32 {
+            self.end
+        }
+
+        // Number of addresses in the range, 2^32 for the whole address space
+        pub fn address_count(&self) -> u64 {
+            self.end as u64 - self.start as u64 + 1
+        }
+
+        // Every address from start to end, lazily. Check address_count before collecting.
+        pub fn addresses(&self) -> impl Iterator<Item = Ipv4Addr> {
+            (self.start..=self.end).map(Ipv4Addr::from)
+        }
+
+        // City if the city is filled in, else Region if the region is, else Country if the
+        // country is, else Unknown. Whitespace-only fields count as empty.
+        pub fn precision(&self) -> Precision {
+            if !self.city.trim().is_empty() {
+                Precision::City
+            } else if !self.region.trim().is_empty() {
+                Precision::Region
+            } else if !self.country.trim().is_empty() {
+                Precision::Country
+            } else {
+                Precision::Unknown
+            }
+        }
+
+        // 64-bit FNV-1a over the bounds as little-endian bytes and the length and bytes of the
+        // country, region and city. Unlike the std hashers it has no random seed, so the same
+        // range hashes the same in every process, on every machine and in every version.
+        pub fn stable_hash(&self) -> u64 {
+            const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+            const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+            let mut hash = OFFSET_BASIS;
+            let mut write = |bytes: &[u8]| {
+                for byte in bytes {
+                    hash ^= *byte as u64;
+                    hash = hash.wrapping_mul(PRIME);
+                }
+            };
+            write(&self.start.to_le_bytes());
+            write(&self.end.to_le_bytes());
+            for text in [&self.country, &self.region, &self.city] {
+                // The length keeps ("ab", "c") and ("a", "bc") apart
+                write(&(text.len() as u64).to_le_bytes());
+                write(text.as_bytes());
+            }
+            hash
+        }
+
+        fn same_location(&self, other: &IpRange) -> bool {
+            self.country == other.country && self.region == other.region && self.city == other.city
+        }
+
+        // The csv row this range was read from, only kept when the Looker was built with keep_raw
+        pub fn raw(&self) -> Option<&StringRecord> {
+            self.extras.as_ref()?.raw.as_ref()
+        }
+
+        // Weighted locations set with with_candidates, empty without any
+        fn candidates(&self) -> &[(Location, f32)] {
+            self.extras.as_ref().map_or(&[], |extras| &extras.candidates)
+        }
+    }
+
+    impl RangeExtras {
+        // Boxed, or None when it holds nothing so the range doesn't allocate for it
+        fn boxed(self) -> Option<Box<Self>> {
+            let empty = self.org.is_none() && self.country_name.is_none() && self.region_code.is_none()
+                && self.latitude.is_none() && self.longitude.is_none() && self.raw.is_none()
+                && self.candidates.is_empty() && self.attributes.is_empty();
+            (!empty).then(|| Box::new(self))
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn deserialize_extras<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Box<RangeExtras>>, D::Error> {
+        <RangeExtras as serde::Deserialize>::deserialize(deserializer).map(RangeExtras::boxed)
+    }
+
+    impl SortedRanges {
+        // Fails if the ranges are not already in order or a range ends before it starts
+        pub fn new(ranges: Vec<IpRange>) -> Result<Self, LookerError> {
+            for (index, range) in ranges.iter().enumerate() {
+                if range.start > range.end {
+                    return Err(LookerError::InvalidRange { index, start: range.start, end: range.end });
+                }
+                if index > 0 && (ranges[index - 1].start, ranges[index - 1].end) > (range.start, range.end) {
+                    return Err(LookerError::Unsorted { index });
+                }
+            }
+            Ok(SortedRanges(ranges.into()))
+        }
+
+        // Sorts the ranges, dropping any that end before they start
+        pub fn from_unsorted(mut ranges: Vec<IpRange>) -> Self {
+            let count = ranges.len();
+            ranges.retain(|range| range.start <= range.end);
+            if ranges.len() != count {
+                log::warn!("Dropped {} IP ranges that end before they start", count - ranges.len());
+            }
+            ranges.sort_by_key(|range| (range.start, range.end));
+            SortedRanges(ranges.into())
+        }
+
+        pub fn as_slice(&self) -> &[IpRange] {
+            &self.0
+        }
+
+        // Copies the ranges out, they may be shared with other clones
+        pub fn into_inner(self) -> Vec<IpRange> {
+            self.0.to_vec()
+        }
+    }
+
+    impl Health {
+        // Non-empty, non-overlapping and covering at least min_coverage (0.0 to 1.0) of the IPv4 space
+        pub fn is_ready(&self, min_coverage: f64) -> bool {
+            self.non_empty && self.non_overlapping && self.coverage >= min_coverage
+        }
+    }
+
+    impl DatabaseDiff {
+        pub fn added_count(&self) -> usize {
+            self.added.len()
+        }
+
+        pub fn removed_count(&self) -> usize {
+            self.removed.len()
+        }
+
+        pub fn changed_count(&self) -> usize {
+            self.changed.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+        }
+    }
+
+    impl FromIterator<IpRange> for SortedRanges {
+        fn from_iter<I: IntoIterator<Item = IpRange>>(iter: I) -> Self {
+            SortedRanges::from_unsorted(iter.into_iter().collect())
+        }
+    }
+
+    impl Extend<IpRange> for SortedRanges {
+        fn extend<I: IntoIterator<Item = IpRange>>(&mut self, iter: I) {
+            // Copy on write, other clones keep the ranges they had
+            let mut ranges = self.0.to_vec();
+            ranges.extend(iter);
+            *self = SortedRanges::from_unsorted(ranges);
+        }
+    }
+
+    impl Deref for SortedRanges {
+        type Target = [IpRange];
+
+        fn deref(&self) -> &[IpRange] {
+            &self.0
+        }
+    }
+
+    impl<S: RangeStore> Looker<S> {
+        // A Looker around a store that was filled elsewhere. It has no file to reload from.
+        pub fn from_store(store: S) -> Self {
+            Looker {
+                file_path: PathBuf::new(),
+                index: store,
+                options: LoadOptions::default(),
+                file_state: None,
+                format: SourceFormat::Csv,
+                load_stats: LoadStats::default(),
+                skip_reserved: false,
+                version: None,
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            }
+        }
+    }
+
+    impl Looker {
+
+        pub fn new(file_path: PathBuf) -> Self {
+
+            let file_state = FileState::read(&file_path);
+            let loaded = file_path.to_str()
+                .ok_or_else(|| LookerError::InvalidFilePath(file_path.clone()))
+                .and_then(|path| read_ip_ranges(path, &LoadOptions::default()));
+            let (ip_ranges, load_stats) = match loaded {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    log::error!("Error reading IP ranges: {}", e);
+                    (SortedRanges::default(), LoadStats::default())
+                }
+            };
+            if ip_ranges.is_empty() {
+                log::warn!("No IP ranges loaded, every lookup will return None!");
+            }
+            Looker {
+                file_path,
+                index: RangeIndex::from(ip_ranges),
+                options: LoadOptions::default(),
+                file_state,
+                format: SourceFormat::Csv,
+                load_stats,
+                skip_reserved: false,
+                version: None,
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            }
+
+        }
+
+        // Like new, but returns an error instead of an empty Looker when the file
+        // can't be read or contains no IP ranges
+        pub fn try_new(file_path: PathBuf) -> Result<Self, LookerError> {
+            LookerBuilder::new()
+                .file_path(file_path)
+                .reject_empty(true)
+                .try_build()
+        }
+
+        pub fn builder() -> LookerBuilder {
+            LookerBuilder::new()
+        }
+
+        // For ranges that don't come from a CSV file, file_path is left empty
+        pub fn from_ranges(ranges: Vec<IpRange>) -> Self {
+            Looker::from_index(RangeIndex::new(ranges))
+        }
+
+        pub fn from_index(index: RangeIndex) -> Self {
+            Looker::from_store(index)
+        }
+
+        // Like look_up, with the reason for a miss
+        pub fn look_up_detailed(&self, ip: &str) -> Result<IpRange, LookupError> {
+            let ip = Ipv4Addr::from_str(ip.trim()).map_err(|_| LookupError::InvalidIp(ip.to_string()))?;
+            let class = classify(&ip);
+            if self.skip_reserved && class.is_special() {
+                return Err(LookupError::Reserved(class));
+            }
+            self.look_up_u32(u32::from(ip)).cloned().ok_or(LookupError::NotFound)
+        }
+
+        // look_up_detailed and look_up_precision in one, for a single match over every outcome
+        pub fn resolve(&self, ip: &Ipv4Addr) -> Resolution {
+            let class = classify(ip);
+            if self.skip_reserved && class.is_special() {
+                return Resolution::Reserved(class);
+            }
+            match self.look_up_precision(ip) {
+                Some((range, precision)) => Resolution::Found { range: Box::new(range), precision },
+                None => Resolution::NotFound,
+            }
+        }
+
+        // Counts from the last load of the file, all zero for a Looker not read from a csv
+        pub fn load_stats(&self) -> LoadStats {
+            self.load_stats
+        }
+
+        pub fn with_version(mut self, version: &str) -> Self {
+            self.version = Some(version.into());
+            self
+        }
+
+        // The version given to LookerBuilder::version or with_version, empty when none was
+        pub fn version(&self) -> &str {
+            self.version.as_deref().unwrap_or("")
+        }
+
+        // The match together with the version of the database that answered
+        pub fn look_up_with_meta(&self, ip: &Ipv4Addr) -> Option<(IpRange, &str)> {
+            self.find(ip).map(|range| (range.clone(), self.version()))
+        }
+
+        // Whether ip is inside the interval given to LookerBuilder::only_within, always true
+        // when the whole database was loaded. A miss for an IP out of scope says nothing about
+        // the full database.
+        pub fn in_scope(&self, ip: &Ipv4Addr) -> bool {
+            match self.options.only_within {
+                Some((start, end)) => (start..=end).contains(&u32::from(*ip)),
+                None => true,
+            }
+        }
+
+        pub fn index(&self) -> &RangeIndex {
+            &self.index
+        }
+
+        pub fn into_index(self) -> RangeIndex {
+            self.index
+        }
+
+        // Reads one JSON object per line with the fields start, end, country, region and city.
+        // Blank lines are skipped, the ranges are sorted the same way as when loading a csv.
+        #[cfg(feature = "serde")]
+        pub fn from_ndjson<R: std::io::Read>(reader: R) -> Result<Self, LookerError> {
+            use std::io::BufRead;
+
+            let mut ip_ranges = Vec::new();
+            for (index, line) in std::io::BufReader::new(reader).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let range: IpRange = serde_json::from_str(&line)
+                    .map_err(|error| LookerError::Json { line: index + 1, error })?;
+                ip_ranges.push(range);
+            }
+
+            Ok(Looker::from_ranges(ip_ranges))
+        }
+
+        // See RangeIndex::with_prefix_index
+        pub fn with_trie(mut self) -> Self {
+            self.index = self.index.with_prefix_index();
+            self
+        }
+
+        // See RangeIndex::with_symbols
+        pub fn with_symbols(mut self) -> Self {
+            self.index = self.index.with_symbols();
+            self
+        }
+
+        // Reads a file with fixed-width columns instead of a csv, blank lines are skipped. A reload
+        // reads it again with the same schema.
+        pub fn from_fixed_width(file_path: PathBuf, schema: &FixedWidthSchema) -> Result<Self, LookerError> {
+            let file_state = FileState::read(&file_path);
+            let mut looker = Looker::from_index(RangeIndex::from(read_fixed_width_ranges(&file_path, schema)?));
+            looker.file_path = file_path;
+            looker.file_state = file_state;
+            looker.format = SourceFormat::FixedWidth(schema.clone());
+            Ok(looker)
+        }
+
+        // Reads a csv straight out of a zip archive, like the ones MaxMind ships. entry is the
+        // full name of the csv inside the archive, nested directories included. Without an entry
+        // the archive must contain exactly one file ending in .csv. Loads with default options.
+        #[cfg(feature = "zip")]
+        pub fn from_zip(path: &Path, entry: Option<&str>) -> Result<Self, LookerError> {
+            let file = std::fs::File::open(path)?;
+            let mut archive = zip::ZipArchive::new(file).map_err(LookerError::Zip)?;
+
+            let name = match entry {
+                Some(entry) => entry.to_string(),
+                None => {
+                    let mut csv_entries = archive.file_names()
+                        .map(|name| name.map(Cow::into_owned))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(LookerError::Zip)?;
+                    csv_entries.retain(|name| name.to_lowercase().ends_with(".csv"));
+                    if csv_entries.len() != 1 {
+                        return Err(LookerError::ZipEntry { csv_entries });
+                    }
+                    csv_entries.remove(0)
+                }
+            };
+
+            let zip_file = archive.by_name(&name).map_err(LookerError::Zip)?;
+            // The uncompressed size is whatever the archive claims, so it is only trusted up to
+            // a csv's usual compression ratio and the estimate is capped
+            let claimed_size = zip_file.size().min(zip_file.compressed_size().saturating_mul(MAX_ZIP_RATIO));
+            let row_estimate = ((claimed_size / AVERAGE_ROW_LENGTH) as usize).min(MAX_ZIP_ROW_ESTIMATE);
+            let (ip_ranges, load_stats) = read_ip_ranges_from(zip_file, row_estimate, &LoadOptions::default())?;
+
+            // file_path is left empty, reload only reads plain csv files
+            let mut looker = Looker::from_index(RangeIndex::from(ip_ranges));
+            looker.load_stats = load_stats;
+            Ok(looker)
+        }
+
+        // Hashes the whole file with SHA-256 and only parses it, with default options, if the hash
+        // matches expected_sha256 (hex, any case). Catches truncated downloads and tampered files.
+        // The ranges are parsed from the same bytes that were hashed.
+        #[cfg(feature = "checksum")]
+        pub fn from_path_verified(file_path: PathBuf, expected_sha256: &str) -> Result<Self, LookerError> {
+            use sha2::{Digest, Sha256};
+
+            let file_state = FileState::read(&file_path);
+            let contents = std::fs::read(&file_path)?;
+            let actual: String = Sha256::digest(&contents).iter().map(|byte| format!("{:02x}", byte)).collect();
+            if !actual.eq_ignore_ascii_case(expected_sha256.trim()) {
+                return Err(LookerError::ChecksumMismatch { expected: expected_sha256.trim().to_lowercase(), actual });
+            }
+
+            let row_estimate = contents.len() / AVERAGE_ROW_LENGTH as usize;
+            let (ip_ranges, load_stats) = read_ip_ranges_from(&contents[..], row_estimate, &LoadOptions::default())?;
+            if ip_ranges.is_empty() {
+                log::warn!("No IP ranges loaded, every lookup will return None!");
+            }
+
+            let mut looker = Looker::from_index(RangeIndex::from(ip_ranges));
+            looker.file_path = file_path;
+            looker.file_state = file_state;
+            looker.load_stats = load_stats;
+            looker.checksum = Some(actual);
+            Ok(looker)
+        }
+
+        // SHA-256 of the loaded file in lowercase hex, only known for a Looker from
+        // from_path_verified. A reload doesn't verify the file again and clears it.
+        #[cfg(feature = "checksum")]
+        pub fn source_checksum(&self) -> Option<String> {
+            self.checksum.clone()
+        }
+
+        // Loads a database written by LookerBuilder::write_binary. reload reads the file as
+        // binary again.
+        pub fn from_binary(file_path: PathBuf) -> Result<Self, LookerError> {
+            Looker::from_binary_file(file_path, SourceFormat::Binary)
+        }
+
+        // Loads a binary dump from another tool, with no header and one record per range:
+        // start and end as big-endian u32, then the country, region and city, each a big-endian
+        // u16 byte length followed by that many bytes of UTF-8. A file ending inside a record fails.
+        // reload reads the file in this format again.
+        pub fn from_network_order_binary(file_path: PathBuf) -> Result<Self, LookerError> {
+            Looker::from_binary_file(file_path, SourceFormat::NetworkOrderBinary)
+        }
+
+        fn from_binary_file(file_path: PathBuf, format: SourceFormat) -> Result<Self, LookerError> {
+            let file_state = FileState::read(&file_path);
+            let mut looker = Looker::from_index(RangeIndex::from(read_binary_ranges(&file_path, &format)?));
+            looker.file_path = file_path;
+            looker.file_state = file_state;
+            looker.format = format;
+            Ok(looker)
+        }
+
+        // Reads the file again with the same options, and in the same format, it was loaded
+        // with. On error the current ranges are kept.
+        pub fn reload(&mut self) -> Result<(), LookerError> {
+            if self.file_path.as_os_str().is_empty() {
+                return Err(LookerError::MissingFilePath);
+            }
+            let path = self.file_path.to_str().ok_or_else(|| LookerError::InvalidFilePath(self.file_path.clone()))?;
+            let file_state = FileState::read(&self.file_path);
+            let (ip_ranges, load_stats) = match &self.format {
+                SourceFormat::Csv => read_ip_ranges(path, &self.options)?,
+                SourceFormat::FixedWidth(schema) => (read_fixed_width_ranges(&self.file_path, schema)?, LoadStats::default()),
+                format => (read_binary_ranges(&self.file_path, format)?, LoadStats::default()),
+            };
+            self.index.ip_ranges = ip_ranges;
+            self.index.rebuild_indexes();
+            #[cfg(feature = "checksum")]
+            {
+                self.checksum = None;
+            }
+            self.file_state = file_state;
+            self.load_stats = load_stats;
+            Ok(())
+        }
+
+        // Only reloads when the file's modification time or size changed since it was
+        // last loaded. Returns whether it was reloaded.
+        pub fn reload_if_modified(&mut self) -> Result<bool, LookerError> {
+            let current = FileState::read(&self.file_path);
+            if current.is_some() && current == self.file_state {
+                return Ok(false);
+            }
+            self.reload()?;
+            Ok(true)
+        }
+
+
+        // Splits into the ranges below ip and the ranges at or above it. A range containing ip
+        // but starting below it is cut in two: start..=ip-1 goes to the lower half and ip..=end
+        // to the upper half, both keeping the location. Neither half has a file path, so reload
+        // returns LookerError::MissingFilePath instead of loading the whole file back.
+        pub fn split_at_ip(self, ip: &Ipv4Addr) -> (Looker, Looker) {
+            let split = u32::from(*ip);
+            let mut lower = Vec::new();
+            let mut upper = Vec::new();
+
+            for range in self.ip_ranges.iter() {
+                if range.end < split {
+                    lower.push(range.clone());
+                } else if range.start >= split {
+                    upper.push(range.clone());
+                } else {
+                    let mut below = range.clone();
+                    below.end = split - 1;
+                    lower.push(below);
+                    let mut above = range.clone();
+                    above.start = split;
+                    upper.push(above);
+                }
+            }
+
+            (self.with_ranges(lower), self.with_ranges(upper))
+        }
+
+        // Splits into n Lookers holding roughly the same number of addresses each, for spreading
+        // the table over n workers by address space. The shards are contiguous in table order and
+        // don't overlap when the ranges don't. A range straddling a boundary is cut there, like in
+        // split_at_ip, with the addresses up to the boundary going to the earlier shard. There are
+        // always n shards, some of them empty when there are fewer addresses than shards, and none
+        // for an n of 0. Like split_at_ip the shards have no file path, so reload and
+        // reload_if_modified return LookerError::MissingFilePath and each shard stays as it was cut.
+        pub fn shard(&self, n: usize) -> Vec<Looker> {
+            if n == 0 {
+                log::error!("Error: cannot split into 0 shards");
+                return Vec::new();
+            }
+            // u128 since the boundary multiplies the total by the shard number
+            let total: u128 = self.ip_ranges.iter().map(|range| range.address_count() as u128).sum();
+            let boundary = |shard: usize| total * shard as u128 / n as u128;
+
+            let mut shards: Vec<Vec<IpRange>> = vec![Vec::new(); n];
+            let mut shard = 0;
+            // Addresses handed out to the shards so far
+            let mut assigned: u128 = 0;
+            for range in self.ip_ranges.iter() {
+                let mut start = range.start as u64;
+                while start <= range.end as u64 {
+                    while shard + 1 < n && assigned >= boundary(shard + 1) {
+                        shard += 1;
+                    }
+                    let left_in_range = range.end as u64 - start + 1;
+                    let take = match shard + 1 < n {
+                        true => ((boundary(shard + 1) - assigned) as u64).min(left_in_range),
+                        false => left_in_range,
+                    };
+                    shards[shard].push(clip_range(range, start as u32, (start + take - 1) as u32));
+                    assigned += take as u128;
+                    start += take;
+                }
+            }
+
+            shards.into_iter().map(|ranges| self.with_ranges(ranges)).collect()
+        }
+
+        // Layers other under this database: where both have a range, this one wins, and
+        // ranges from other are cut down to the addresses this one doesn't cover.
+        // Every range keeps its source, so lookups tell which database answered. Like split_at_ip,
+        // the result has no file path to reload from.
+        pub fn merge(&self, other: &Looker) -> Looker {
+            let mut ranges = self.ip_ranges.to_vec();
+
+            for range in other.ip_ranges.iter() {
+                // u64 so the address after u32::MAX doesn't overflow
+                let mut uncovered_from = range.start as u64;
+                for covering in self.look_up_interval(range.start, range.end) {
+                    if covering.start as u64 > uncovered_from {
+                        let mut gap = range.clone();
+                        gap.start = uncovered_from as u32;
+                        gap.end = covering.start - 1;
+                        ranges.push(gap);
+                    }
+                    uncovered_from = uncovered_from.max(covering.end as u64 + 1);
+                }
+                if uncovered_from <= range.end as u64 {
+                    let mut rest = range.clone();
+                    rest.start = uncovered_from as u32;
+                    ranges.push(rest);
+                }
+            }
+
+            self.with_ranges(ranges)
+        }
+
+        // A country-only copy where adjacent or overlapping ranges with the same country are
+        // merged into one. Region, city and coordinates are dropped. The compression ratio
+        // (ranges before / ranges after) is logged at info level. The copy has no file path to
+        // reload from.
+        pub fn collapse_to_country(&self) -> Looker {
+            let mut ranges: Vec<IpRange> = Vec::new();
+
+            for range in self.ip_ranges.iter() {
+                if let Some(last) = ranges.last_mut() {
+                    if last.country == range.country && range.start as u64 <= last.end as u64 + 1 {
+                        last.end = last.end.max(range.end);
+                        continue;
+                    }
+                }
+                let mut collapsed = IpRange::new(range.start, range.end, range.country.clone(), String::new(), String::new());
+                collapsed.source = range.source.clone();
+                ranges.push(collapsed);
+            }
+
+            if !ranges.is_empty() {
+                log::info!("Collapsed {} IP ranges into {} by country, a ratio of {:.2}", self.len(), ranges.len(), self.len() as f64 / ranges.len() as f64);
+            }
+            self.with_ranges(ranges)
+        }
+
+        // A Looker over other ranges that keeps the indexes of this one. It doesn't keep the file
+        // path: the ranges no longer match the file, so reload returns LookerError::MissingFilePath
+        // rather than replacing them with the whole file.
+        fn with_ranges(&self, ranges: Vec<IpRange>) -> Looker {
+            let mut looker = Looker::from_ranges(ranges);
+            looker.skip_reserved = self.skip_reserved;
+            looker.version = self.version.clone();
+            looker.index.prefix_index = self.prefix_index.clone();
+            looker.index.symbols = self.symbols.clone();
+            looker.index.rebuild_indexes();
+            looker
+        }
+
+    }
+
+    impl LazyLooker {
+        pub fn open(file_path: PathBuf) -> Result<Self, LookerError> {
+            let file = std::fs::File::open(&file_path)?;
+            let file_len = file.metadata()?.len();
+            let mut rdr = ReaderBuilder::new().from_reader(&file);
+
+            let mut rows: Vec<LazyRow> = Vec::new();
+            let mut record = ByteRecord::new();
+            while rdr.read_byte_record(&mut record).map_err(csv_error)? {
+                let (row, offset) = record.position().map_or((0, 0), |position| (position.line(), position.byte()));
+                let start = parse_field(&strict_field(&record, 0, row, "start")?, row, "start", "u32", |value| value.parse().ok())?;
+                let end = parse_field(&strict_field(&record, 1, row, "end")?, row, "end", "u32", |value| value.parse().ok())?;
+                // A row runs until the next one starts
+                if let Some(previous) = rows.last_mut() {
+                    previous.len = offset - previous.offset;
+                }
+                rows.push(LazyRow { start, end, offset, len: 0 });
+            }
+            if let Some(last) = rows.last_mut() {
+                last.len = file_len - last.offset;
+            }
+
+            let count = rows.len();
+            rows.retain(|row| row.start <= row.end);
+            if rows.len() != count {
+                log::warn!("Dropped {} IP ranges that end before they start", count - rows.len());
+            }
+            rows.sort_by_key(|row| (row.start, row.end));
+            if rows.is_empty() {
+                log::warn!("No IP ranges loaded, every lookup will return None!");
+            }
+
+            // The reader borrows the file, so it has to go before the file is moved
+            drop(rdr);
+            Ok(LazyLooker { file_path, rows, file: Mutex::new(file), cache: Mutex::new(HashMap::new()) })
+        }
+
+        pub fn len(&self) -> usize {
+            self.rows.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.rows.is_empty()
+        }
+
+        // Number of rows parsed so far
+        pub fn cached_rows(&self) -> usize {
+            self.cache.lock().unwrap_or_else(|e| e.into_inner()).len()
+        }
+
+        fn range_at(&self, index: usize) -> Result<Option<IpRange>, LookerError> {
+            use std::io::{Read, Seek, SeekFrom};
+
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(range) = cache.get(&index) {
+                return Ok(Some(range.clone()));
+            }
+
+            let row = self.rows[index];
+            let mut bytes = vec![0; row.len as usize];
+            {
+                let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+                file.seek(SeekFrom::Start(row.offset))?;
+                file.read_exact(&mut bytes)?;
+            }
+            let options = LoadOptions { has_headers: false, ..LoadOptions::default() };
+            let (ranges, _) = read_ip_ranges_from(&bytes[..], 1, &options)?;
+            let range = ranges.first().cloned();
+            if let Some(range) = &range {
+                cache.insert(index, range.clone());
+            }
+            Ok(range)
+        }
+    }
+
+    impl RangeIndex {
+
+        // The ranges are sorted, ranges ending before they start are dropped
+        pub fn new(ranges: Vec<IpRange>) -> Self {
+            RangeIndex::from(SortedRanges::from_unsorted(ranges))
+        }
+
+        // Indexes the ranges by their /16 prefix so a lookup only binary searches the few
+        // ranges overlapping the IP's /16 block instead of the whole table
+        pub fn with_prefix_index(mut self) -> Self {
+            self.prefix_index = Some(Arc::new(PrefixIndex::build(&self.ip_ranges, &self.max_end)));
+            self
+        }
+
+        // A shared handle to the current ranges, no rows are copied. The snapshot stays the
+        // same when the Looker is reloaded, and keeps the old ranges in memory for as long
+        // as it is held, on top of the newly loaded ones.
+        pub fn snapshot(&self) -> Arc<[IpRange]> {
+            self.ip_ranges.0.clone()
+        }
+
+        pub fn has_prefix_index(&self) -> bool {
+            self.prefix_index.is_some()
+        }
+
+        // Recomputes the prefix index, if enabled, and the overlap bookkeeping used by
+        // look_up_all from the current ip_ranges. Replacing ip_ranges directly leaves both
+        // describing the old ranges, so call this afterwards. Everything else that changes the
+        // ranges, like Looker::reload, merge, split_at_ip or Extend, already does.
+        pub fn rebuild_indexes(&mut self) {
+            self.max_end = self.ip_ranges.iter()
+                .scan(0, |max_end, range| {
+                    *max_end = range.end.max(*max_end);
+                    Some(*max_end)
+                })
+                .collect();
+            if self.has_prefix_index() {
+                self.prefix_index = Some(Arc::new(PrefixIndex::build(&self.ip_ranges, &self.max_end)));
+            }
+            if self.symbols.is_some() {
+                self.intern_symbols();
+            }
+        }
+
+        // Interns every country, region and city into a u32 id shared by all equal strings, so
+        // hot loops can compare and hash IpRange::country_id and friends instead of strings.
+        // resolve_symbol turns an id back into its string. The strings stay in the ranges too,
+        // this trades some memory and load time for cheaper comparisons. Ids are numbered in
+        // order of first appearance and are only comparable within one index.
+        pub fn with_symbols(mut self) -> Self {
+            self.intern_symbols();
+            self
+        }
+
+        // The string of a symbol id from IpRange::country_id, region_id or city_id, empty for
+        // an id this index doesn't know
+        pub fn resolve_symbol(&self, id: u32) -> &str {
+            self.symbols.as_ref()
+                .and_then(|symbols| symbols.get(id as usize))
+                .map_or("", |symbol| symbol)
+        }
+
+        fn intern_symbols(&mut self) {
+            // The ids are worked out while the strings are only borrowed, then written into the
+            // ranges in place. The ranges are only copied when a clone or snapshot shares them.
+            let mut ids: HashMap<&str, u32> = HashMap::new();
+            let mut symbols: Vec<Box<str>> = Vec::new();
+            let range_ids: Vec<[u32; 3]> = self.ip_ranges.iter().map(|range| {
+                [&range.country, &range.region, &range.city].map(|value| {
+                    *ids.entry(value.as_str()).or_insert_with(|| {
+                        symbols.push(value.as_str().into());
+                        symbols.len() as u32 - 1
+                    })
+                })
+            }).collect();
+
+            for (range, ids) in Arc::make_mut(&mut self.ip_ranges.0).iter_mut().zip(range_ids) {
+                range.symbol_ids = Some(ids);
+            }
+            self.symbols = Some(symbols.into());
+        }
+
+        // The range containing the address, as a u32 like the bounds of an IpRange
+        pub fn look_up_u32(&self, ip: u32) -> Option<&IpRange> {
+            self.find_index(ip).map(|index| &self.ip_ranges[index])
+        }
+
+        // For raw address bytes, like from a packet capture, without going through Ipv4Addr
+        pub fn look_up_octets(&self, octets: [u8; 4]) -> Option<&IpRange> {
+            self.look_up_u32(from_octets(octets))
+        }
+
+        // Like IpLookup::look_up, but never logs: invalid input and misses are only reported
+        // through the error, for callers where misses are expected and frequent
+        pub fn try_look_up(&self, ip: &str) -> Result<IpRange, LookupError> {
+            let ip = Ipv4Addr::from_str(ip.trim()).map_err(|_| LookupError::InvalidIp(ip.to_string()))?;
+            self.try_look_up_ipv4(&ip)
+        }
+
+        pub fn try_look_up_ipv4(&self, ip: &Ipv4Addr) -> Result<IpRange, LookupError> {
+            self.find(ip).cloned().ok_or(LookupError::NotFound)
+        }
+
+        pub fn len(&self) -> usize {
+            self.ip_ranges.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.ip_ranges.is_empty()
+        }
+
+        // Case-insensitive check of the matched country, without cloning the range
+        pub fn in_countries(&self, ip: &Ipv4Addr, countries: &[&str]) -> bool {
+            match self.find(ip) {
+                Some(range) => countries.iter().any(|country| eq_ignore_case(&range.country, country)),
+                None => false,
+            }
+        }
+
+        // Checks a country claimed for ip, compared case-insensitively like in_countries
+        pub fn verify_location(&self, ip: &Ipv4Addr, claimed_country: &str) -> LocationMatch {
+            match self.find(ip) {
+                Some(range) if eq_ignore_case(&range.country, claimed_country) => LocationMatch::Match,
+                Some(range) => LocationMatch::Mismatch { actual: range.country.clone() },
+                None => LocationMatch::Unknown,
+            }
+        }
+
+        pub fn in_any_region(&self, ip: &Ipv4Addr, regions: &[&str]) -> bool {
+            match self.find(ip) {
+                Some(range) => regions.iter().any(|region| eq_ignore_case(&range.region, region)),
+                None => false,
+            }
+        }
+
+        pub fn look_up_decimal_str(&self, s: &str) -> Option<IpRange> {
+            let ip_decimal_to_use = match s.trim().parse::<u32>() {
+                Err(e) => {
+                    log::error!("Error: could not parse '{}' as a decimal IP: {}", s, e);
+                    return None;
+                },
+                Ok(ip_decimal) => {
+                    ip_decimal
+                }
+            };
+
+            match self.find_index(ip_decimal_to_use).map(|index| self.ip_ranges[index].clone()) {
+                Some(range) => {
+                    log::trace!("IP is in range: {:?}", range);
+                    Some(range)
+                },
+                None => {
+                    log::trace!("IP not found in any range");
+                    None
+                }
+            }
+        }
+
+        // Returns the range, the offset of the IP within it and the size of the range.
+        // The size saturates at u32::MAX for a range covering the whole address space.
+        pub fn look_up_with_offset(&self, ip: &Ipv4Addr) -> Option<(IpRange, u32, u32)> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            let range = self.find(ip)?;
+            let offset = ip_decimal - range.start;
+            let size = (range.end - range.start).saturating_add(1);
+            Some((range.clone(), offset, size))
+        }
+
+        // How far the IP is from the start and from the end of its range, (0, 0) for a
+        // single address range
+        pub fn boundary_distances(&self, ip: &Ipv4Addr) -> Option<(u32, u32)> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            let range = self.look_up_u32(ip_decimal)?;
+            Some((ip_decimal - range.start, range.end - ip_decimal))
+        }
+
+        // Only looks up ip if it parses as the given family, so an IPv4-mapped IPv6 address
+        // like ::ffff:1.2.3.4 is rejected when V4 is asked for. The database only holds IPv4
+        // ranges, so V6 addresses never match.
+        pub fn look_up_with_family(&self, ip: &str, family: IpFamily) -> Option<IpRange> {
+            let ip = match IpAddr::from_str(ip.trim()) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    return None;
+                },
+                Ok(ip) => ip
+            };
+
+            match (ip, family) {
+                (IpAddr::V4(ip), IpFamily::V4) => self.look_up_ipv4(&ip),
+                (IpAddr::V6(ip), IpFamily::V6) => {
+                    log::trace!("No IPv6 ranges loaded, {} not found", ip);
+                    None
+                },
+                (ip, family) => {
+                    log::error!("Error: {} is not an {:?} address", ip, family);
+                    None
+                }
+            }
+        }
+
+        // The matched range together with its index in ip_ranges
+        pub fn look_up_indexed(&self, ip: &Ipv4Addr) -> Option<(usize, &IpRange)> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            self.find_index(ip_decimal).map(|index| (index, &self.ip_ranges[index]))
+        }
+
+        // look_up_indexed plus whether the match is the first and the last range in the table,
+        // for paging through the ranges without asking for len separately
+        pub fn look_up_positioned(&self, ip: &Ipv4Addr) -> Option<(usize, &IpRange, bool, bool)> {
+            let (index, range) = self.look_up_indexed(ip)?;
+            Some((index, range, index == 0, index + 1 == self.ip_ranges.len()))
+        }
+
+        // One result per IP, in the same order
+        pub fn look_up_many(&self, ips: &[Ipv4Addr]) -> Vec<Option<IpRange>> {
+            ips.iter().map(|ip| self.look_up_ipv4(ip)).collect()
+        }
+
+        // look_up_many for a batch already sorted ascending. Instead of a binary search over the
+        // whole table per IP it moves a cursor forward through the ranges in step with the batch,
+        // galloping past the ranges between two IPs, so each IP costs about the log of how many
+        // ranges it skips. The input must be sorted: the cursor never moves back, so an IP smaller
+        // than the one before it is only found if it is in the range the cursor is on, otherwise
+        // it is reported as a miss even if a range contains it.
+        pub fn look_up_sorted_batch(&self, sorted_ips: &[Ipv4Addr]) -> Vec<Option<&IpRange>> {
+            let ranges = &self.ip_ranges[..];
+            let mut cursor = 0;
+            sorted_ips.iter().map(|ip| {
+                let ip = u32::from(*ip);
+                if cursor < ranges.len() && ranges[cursor].end < ip {
+                    // Double the step until it reaches a range ending at or after ip, then binary
+                    // search the last stretch. ranges[cursor + step / 2] always ends before ip.
+                    let mut step = 1;
+                    while cursor + step < ranges.len() && ranges[cursor + step].end < ip {
+                        step *= 2;
+                    }
+                    let first = cursor + step / 2 + 1;
+                    let last = (cursor + step + 1).min(ranges.len());
+                    cursor = first + ranges[first..last].partition_point(|range| range.end < ip);
+                }
+                ranges.get(cursor).filter(|range| range.start <= ip)
+            }).collect()
+        }
+
+        // look_up_many spread over the rayon thread pool. The results are collected from an
+        // indexed parallel map, so result i is always the lookup of ips[i].
+        #[cfg(feature = "rayon")]
+        pub fn par_look_up_many(&self, ips: &[Ipv4Addr]) -> Vec<Option<IpRange>> {
+            use rayon::prelude::*;
+
+            ips.par_iter().map(|ip| self.look_up_ipv4(ip)).collect()
+        }
+
+        // Like look_up_many, but refuses batches of more than max IPs up front. Each lookup is
+        // a binary search and already fast, the budget only caps the total work of one call.
+        pub fn look_up_many_with_budget(&self, ips: &[Ipv4Addr], max: usize) -> Result<Vec<Option<IpRange>>, BudgetExceeded> {
+            if ips.len() > max {
+                return Err(BudgetExceeded { requested: ips.len(), max });
+            }
+            Ok(self.look_up_many(ips))
+        }
+
+        // Looks up every IP received on input and sends it with its result to output, until
+        // input is closed or output is dropped. Blocks, so run it on its own thread with a
+        // clone of the Looker, clones are cheap.
+        #[cfg(feature = "stream")]
+        pub fn lookup_stream(&self, input: std::sync::mpsc::Receiver<Ipv4Addr>, output: std::sync::mpsc::Sender<(Ipv4Addr, Option<IpRange>)>) {
+            for ip in input {
+                if output.send((ip, self.look_up_ipv4(&ip))).is_err() {
+                    log::trace!("Lookup stream output closed, stopping");
+                    return;
+                }
+            }
+        }
+
+        // The match with the ranges right before and after it in the table, from one search.
+        // None on a miss, see next_range_after for the neighbours of a gap.
+        pub fn look_up_with_context(&self, ip: &Ipv4Addr) -> Option<(Option<&IpRange>, &IpRange, Option<&IpRange>)> {
+            let (index, range) = self.look_up_indexed(ip)?;
+            let previous = index.checked_sub(1).map(|previous| &self.ip_ranges[previous]);
+            Some((previous, range, self.ip_ranges.get(index + 1)))
+        }
+
+        // Number of the given IPs falling in each range, keyed by range index. Ranges that
+        // weren't hit are left out.
+        pub fn bucket_counts(&self, ips: &[Ipv4Addr]) -> HashMap<usize, u64> {
+            let mut counts = HashMap::new();
+            for ip in ips {
+                if let Some((index, _)) = self.look_up_indexed(ip) {
+                    *counts.entry(index).or_insert(0) += 1;
+                }
+            }
+            counts
+        }
+
+        // The match as a GeoJSON Point feature with the location fields as properties.
+        // None when there is no match or the range has no coordinates.
+        #[cfg(feature = "serde")]
+        pub fn look_up_geojson(&self, ip: &Ipv4Addr) -> Option<String> {
+            let range = self.find(ip)?;
+            let (latitude, longitude) = range.coordinates()?;
+
+            let feature = serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [longitude, latitude],
+                },
+                "properties": {
+                    "ip": ip.to_string(),
+                    "country": range.country,
+                    "region": range.region,
+                    "city": range.city,
+                },
+            });
+            Some(feature.to_string())
+        }
+
+        // The countries any of the IPs matched, misses are skipped. Consumes ips lazily.
+        pub fn distinct_countries<I: IntoIterator<Item = Ipv4Addr>>(&self, ips: I) -> BTreeSet<String> {
+            let mut countries = BTreeSet::new();
+            for ip in ips {
+                if let Some(country) = self.country_of(&ip) {
+                    if !countries.contains(country) {
+                        countries.insert(country.to_string());
+                    }
+                }
+            }
+            countries
+        }
+
+        // Tallies the IPs by matched country, misses are counted under "unknown"
+        pub fn country_breakdown<I: Iterator<Item = Ipv4Addr>>(&self, ips: I) -> BTreeMap<String, u64> {
+            let mut breakdown = BTreeMap::new();
+            for ip in ips {
+                let country = self.country_of(&ip).unwrap_or(UNKNOWN_COUNTRY);
+                match breakdown.get_mut(country) {
+                    Some(count) => *count += 1,
+                    None => {
+                        breakdown.insert(country.to_string(), 1);
+                    }
+                }
+            }
+            breakdown
+        }
+
+        // Number of ranges per country. Countries that only differ in case or whitespace are
+        // counted together under the first spelling seen, trimmed and with single spaces.
+        pub fn country_range_counts(&self) -> BTreeMap<String, u64> {
+            self.ranges_by_country().into_iter()
+                .map(|(country, ranges)| (country, ranges.len() as u64))
+                .collect()
+        }
+
+        // Writes the number of ranges and distinct addresses of every country, grouped like
+        // country_range_counts and ordered by country. Csv has a country,ranges,addresses
+        // header, Json is an array of objects with those keys.
+        pub fn write_country_report<W: std::io::Write>(&self, writer: W, format: ReportFormat) -> Result<(), LookerError> {
+            let report: Vec<(String, u64, u64)> = self.ranges_by_country().into_iter()
+                .map(|(country, ranges)| {
+                    let count = ranges.len() as u64;
+                    (country, count, count_covered(ranges.into_iter()))
+                })
+                .collect();
+
+            match format {
+                ReportFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(writer);
+                    writer.write_record(["country", "ranges", "addresses"])?;
+                    for (country, ranges, addresses) in &report {
+                        writer.write_record([country.as_str(), &ranges.to_string(), &addresses.to_string()])?;
+                    }
+                    writer.flush()?;
+                },
+                #[cfg(feature = "serde")]
+                ReportFormat::Json => {
+                    let rows: Vec<serde_json::Value> = report.iter()
+                        .map(|(country, ranges, addresses)| serde_json::json!({ "country": country, "ranges": ranges, "addresses": addresses }))
+                        .collect();
+                    serde_json::to_writer(writer, &rows).map_err(|e| LookerError::Io(e.into()))?;
+                },
+            }
+            Ok(())
+        }
+
+        // The ranges of every country in table order. Countries that only differ in case or
+        // whitespace are grouped under the first spelling seen, trimmed and with single spaces.
+        fn ranges_by_country(&self) -> BTreeMap<String, Vec<&IpRange>> {
+            let mut groups: HashMap<String, (String, Vec<&IpRange>)> = HashMap::new();
+            for range in self.ip_ranges.iter() {
+                let country = range.country.split_whitespace().collect::<Vec<_>>().join(" ");
+                groups.entry(country.to_lowercase()).or_insert((country, Vec::new())).1.push(range);
+            }
+            groups.into_values().collect()
+        }
+
+        // Spans of consecutive indices into ip_ranges whose ranges are in country, matched
+        // case-insensitively, in table order. Iterate ip_ranges[span] to walk a country's
+        // ranges without copying them.
+        pub fn country_range_indices(&self, country: &str) -> Vec<Range<usize>> {
+            let mut spans: Vec<Range<usize>> = Vec::new();
+            for (index, range) in self.ip_ranges.iter().enumerate() {
+                if !eq_ignore_case(&range.country, country) {
+                    continue;
+                }
+                match spans.last_mut() {
+                    Some(span) if span.end == index => span.end += 1,
+                    _ => spans.push(index..index + 1),
+                }
+            }
+            spans
+        }
+
+        // Every range whose country is none of excluded, matched case-insensitively, in table
+        // order. All ranges for an empty list.
+        pub fn ranges_excluding_countries(&self, excluded: &[&str]) -> Vec<&IpRange> {
+            self.ip_ranges.iter()
+                .filter(|range| !excluded.iter().any(|country| eq_ignore_case(&range.country, country)))
+                .collect()
+        }
+
+        // The ranges in country, matched case-insensitively, borrowed in table order
+        pub fn ranges_in_country<'a>(&'a self, country: &'a str) -> impl Iterator<Item = &'a IpRange> + 'a {
+            self.ip_ranges.iter().filter(move |range| eq_ignore_case(&range.country, country))
+        }
+
+        // The k ranges whose coordinates are closest to latitude, longitude by great-circle
+        // distance, closest first. Ranges without coordinates are skipped. One O(n) scan picks
+        // the k closest and only those are sorted, so each call costs O(n + k log k).
+        pub fn nearest_to(&self, latitude: f64, longitude: f64, k: usize) -> Vec<&IpRange> {
+            if k == 0 {
+                return Vec::new();
+            }
+            let mut by_distance: Vec<(f64, usize, &IpRange)> = self.ip_ranges.iter().enumerate()
+                .filter_map(|(index, range)| range.coordinates().map(|(lat, lon)| (haversine_km((latitude, longitude), (lat, lon)), index, range)))
+                .collect();
+            // Of equally distant ranges the first in the table comes first
+            let closer = |a: &(f64, usize, &IpRange), b: &(f64, usize, &IpRange)| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1));
+            if k < by_distance.len() {
+                by_distance.select_nth_unstable_by(k - 1, closer);
+                by_distance.truncate(k);
+            }
+            by_distance.sort_unstable_by(closer);
+            by_distance.into_iter().map(|(_, _, range)| range).collect()
+        }
+
+        // Every country code seen with more than one distinct country name, mapped to those
+        // names. Only ranges loaded with LookerBuilder::country_name_column have a name.
+        pub fn country_name_conflicts(&self) -> HashMap<String, BTreeSet<String>> {
+            let mut names: HashMap<String, BTreeSet<String>> = HashMap::new();
+            for range in self.ip_ranges.iter() {
+                if let Some(country_name) = range.country_name() {
+                    names.entry(range.country.clone()).or_default().insert(country_name.to_string());
+                }
+            }
+            names.retain(|_, names| names.len() > 1);
+            names
+        }
+
+        // Borrow a single field of the match without cloning the whole range
+        pub fn country_of(&self, ip: &Ipv4Addr) -> Option<&str> {
+            self.find(ip).map(|range| range.country.as_str())
+        }
+
+        pub fn region_of(&self, ip: &Ipv4Addr) -> Option<&str> {
+            self.find(ip).map(|range| range.region.as_str())
+        }
+
+        pub fn city_of(&self, ip: &Ipv4Addr) -> Option<&str> {
+            self.find(ip).map(|range| range.city.as_str())
+        }
+
+        pub fn org_of(&self, ip: &Ipv4Addr) -> Option<&str> {
+            self.find(ip)?.org()
+        }
+
+        // Score between 0.0 and 1.0, the average of how narrow the range is and how complete its
+        // location is: 0.5 * (1 - log2(size) / 32) + 0.5 * (filled fields of country, region and city) / 3.
+        // A single address with all three fields scores 1.0, the whole address space without a location 0.0.
+        pub fn look_up_scored(&self, ip: &Ipv4Addr) -> Option<(IpRange, f32)> {
+            let range = self.find(ip)?;
+            let size = (range.end - range.start) as f64 + 1.0;
+            let narrowness = 1.0 - size.log2() / 32.0;
+            let filled = [&range.country, &range.region, &range.city].iter().filter(|field| !field.is_empty()).count();
+            let completeness = filled as f64 / 3.0;
+
+            Some((range.clone(), (0.5 * narrowness + 0.5 * completeness) as f32))
+        }
+
+        // Every weighted location of the match, highest weight first. A range loaded without
+        // candidate columns has its own location as the single candidate, weighted 1.0.
+        pub fn look_up_candidates(&self, ip: &Ipv4Addr) -> Option<Vec<(Location, f32)>> {
+            let range = self.find(ip)?;
+            if range.candidates().is_empty() {
+                return Some(vec![(range.location(), 1.0)]);
+            }
+            Some(range.candidates().to_vec())
+        }
+
+        // The match together with how specific its location is, see IpRange::precision
+        pub fn look_up_precision(&self, ip: &Ipv4Addr) -> Option<(IpRange, Precision)> {
+            let range = self.find(ip)?;
+            Some((range.clone(), range.precision()))
+        }
+
+        // Writes the match into out, reusing its string buffers instead of cloning the range.
+        // Returns false and clears out on a miss.
+        pub fn look_up_into(&self, ip: &Ipv4Addr, out: &mut IpRangeBuf) -> bool {
+            match self.find(ip) {
+                Some(range) => {
+                    out.start = range.start;
+                    out.end = range.end;
+                    out.country.clone_from(&range.country);
+                    out.region.clone_from(&range.region);
+                    out.city.clone_from(&range.city);
+                    true
+                },
+                None => {
+                    out.clear();
+                    false
+                }
+            }
+        }
+
+        // The match with its IpRange::stable_hash, for keying caches shared between processes
+        pub fn look_up_hashed(&self, ip: &Ipv4Addr) -> Option<(IpRange, u64)> {
+            let range = self.find(ip)?;
+            Some((range.clone(), range.stable_hash()))
+        }
+
+        // Both still clone the matched range on a hit, the default is only used on a miss
+        pub fn look_up_or(&self, ip: &Ipv4Addr, default: &IpRange) -> IpRange {
+            self.look_up_ipv4(ip).unwrap_or_else(|| default.clone())
+        }
+
+        pub fn look_up_or_else(&self, ip: &Ipv4Addr, f: impl FnOnce() -> IpRange) -> IpRange {
+            self.look_up_ipv4(ip).unwrap_or_else(f)
+        }
+
+        // The match unless it is anycast, where the location of the range is only one of many
+        pub fn look_up_non_anycast(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+            self.find(ip).filter(|range| !range.anycast).cloned()
+        }
+
+        // The match, or on a miss a range covering only ip with guess_country as its country
+        // and an empty region and city. The made up range is_synthetic, real matches never are.
+        pub fn look_up_or_guess(&self, ip: &Ipv4Addr, guess_country: &str) -> IpRange {
+            self.look_up_ipv4(ip).unwrap_or_else(|| {
+                let ip = u32::from(*ip);
+                let mut guess = IpRange::new(ip, ip, guess_country.to_string(), String::new(), String::new());
+                guess.synthetic = true;
+                guess
+            })
+        }
+
+        // All ranges overlapping the inclusive interval [start, end]
+        pub fn look_up_interval(&self, start: u32, end: u32) -> Vec<&IpRange> {
+            if start > end {
+                log::error!("Error: interval start {} is after its end {}", start, end);
+                return Vec::new();
+            }
+
+            self.interval_indices(start, end).map(|index| &self.ip_ranges[index]).collect()
+        }
+
+        // Indices of the ranges overlapping [start, end], ascending. The search starts at the
+        // first range whose max_end reaches start rather than whose own end does, so a long
+        // range starting before a shorter one isn't skipped when ranges overlap.
+        fn interval_indices(&self, start: u32, end: u32) -> impl Iterator<Item = usize> + '_ {
+            let first = self.max_end.partition_point(|max_end| *max_end < start);
+            let last = first + self.ip_ranges[first..].partition_point(|range| range.start <= end);
+            (first..last).filter(move |index| self.ip_ranges[*index].end >= start)
+        }
+
+        // Every range overlapping at least one of the CIDRs, each only once and in table order,
+        // however many of the CIDRs overlap it. CIDRs that don't parse are returned separately
+        // as LookupError::InvalidIp and don't stop the others.
+        pub fn look_up_cidrs_distinct(&self, cidrs: &[&str]) -> (Vec<&IpRange>, Vec<LookupError>) {
+            let mut indices = HashSet::new();
+            let mut errors = Vec::new();
+            for cidr in cidrs {
+                match parse_cidr(cidr) {
+                    Err(_) => errors.push(LookupError::InvalidIp(cidr.to_string())),
+                    Ok((start, end)) => indices.extend(self.interval_indices(start, end)),
+                }
+            }
+
+            let mut indices: Vec<usize> = indices.into_iter().collect();
+            indices.sort_unstable();
+            (indices.into_iter().map(|index| &self.ip_ranges[index]).collect(), errors)
+        }
+
+        // look_up_interval with every range cut down to the part inside [start, end], keeping
+        // its location. Summing address_count over the result gives the covered part of the interval.
+        pub fn look_up_interval_clipped(&self, start: u32, end: u32) -> Vec<IpRange> {
+            self.look_up_interval(start, end).into_iter().map(|range| clip_range(range, start, end)).collect()
+        }
+
+        // The match clipped to the block of this prefix length containing ip, like the part of
+        // the range inside the /24 of ip, for rolling results up to one granularity. A prefix
+        // length above 32 is read as 32.
+        pub fn look_up_within_prefix(&self, ip: &Ipv4Addr, prefix_len: u8) -> Option<IpRange> {
+            let range = self.find(ip)?;
+            let mask = u32::MAX.checked_shl(32 - prefix_len.min(32) as u32).unwrap_or(0);
+            let start = u32::from(*ip) & mask;
+            Some(clip_range(range, start, start | !mask))
+        }
+
+        // Every range containing ip, in table order, for databases where ranges overlap, like
+        // country-level rows with city-level rows inside them. The other lookups assume ranges
+        // don't overlap and return just one of them.
+        pub fn look_up_all(&self, ip: &Ipv4Addr) -> Vec<&IpRange> {
+            let ip_decimal = u32::from(*ip);
+            let mut found = Vec::new();
+            let mut index = self.ip_ranges.partition_point(|range| range.start <= ip_decimal);
+            while index > 0 {
+                index -= 1;
+                if self.max_end[index] < ip_decimal {
+                    break;
+                }
+                if self.ip_ranges[index].end >= ip_decimal {
+                    found.push(&self.ip_ranges[index]);
+                }
+            }
+            found.reverse();
+            found
+        }
+
+        // The narrowest range containing ip, so a city-level row is preferred over the
+        // country-level row around it and the country is still found when no city row matches.
+        // Of equally narrow ranges the first in the table wins.
+        pub fn look_up_best(&self, ip: &Ipv4Addr) -> Option<&IpRange> {
+            let mut best: Option<&IpRange> = None;
+            for range in self.look_up_all(ip) {
+                if best.is_none_or(|best| range.address_count() < best.address_count()) {
+                    best = Some(range);
+                }
+            }
+            best
+        }
+
+        // Longest-prefix match the way a router resolves it: every range containing ip is split
+        // into its aligned CIDR blocks, and the range whose block around ip has the longest
+        // prefix wins. Of equally long prefixes the first in the table wins. This usually agrees
+        // with look_up_best but not always. With 10.0.0.0-10.0.0.255 (a /24) and
+        // 10.0.0.255-10.0.2.255 (513 addresses, whose first block is 10.0.0.255/32), the
+        // narrowest range for 10.0.0.255 is the /24, the longest prefix is the wider range's /32.
+        pub fn look_up_longest_prefix(&self, ip: &Ipv4Addr) -> Option<&IpRange> {
+            let ip_decimal = u32::from(*ip);
+            let mut best: Option<(&IpRange, u32)> = None;
+            for range in self.look_up_all(ip) {
+                let prefix = prefix_len_at(range, ip_decimal);
+                if best.is_none_or(|(_, best_prefix)| prefix > best_prefix) {
+                    best = Some((range, prefix));
+                }
+            }
+            best.map(|(range, _)| range)
+        }
+
+        // The first range starting after ip, whether or not ip itself is in a range.
+        // Together with look_up_indexed this walks the table in order from any address.
+        pub fn next_range_after(&self, ip: &Ipv4Addr) -> Option<&IpRange> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            let index = self.ip_ranges.partition_point(|range| range.start <= ip_decimal);
+            self.ip_ranges.get(index)
+        }
+
+        // Whether every covered address in a CIDR like 10.0.0.0/24 has the same country, with
+        // FieldSet::CountryOnly, or the same country, region and city, with FieldSet::All.
+        // Addresses in no range are ignored. None when the CIDR is invalid or nothing in it is covered.
+        pub fn subnet_is_homogeneous(&self, cidr: &str, granularity: FieldSet) -> Option<bool> {
+            let (start, end) = match parse_cidr(cidr) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    return None;
+                },
+                Ok(bounds) => bounds
+            };
+
+            let ranges = self.look_up_interval(start, end);
+            let first = ranges.first()?;
+            Some(ranges.iter().all(|range| match granularity {
+                FieldSet::All => range.same_location(first),
+                FieldSet::CountryOnly => range.country == first.country,
+            }))
+        }
+
+        // Summarizes the locations of every address in a CIDR like 10.0.0.0/24. Works on the
+        // ranges overlapping the block rather than on each address, so a block larger than a /16,
+        // or even 0.0.0.0/0, costs no more than the number of ranges in it.
+        pub fn summarize_block(&self, network: &str) -> Option<BlockSummary> {
+            let (start, end) = match parse_cidr(network) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    return None;
+                },
+                Ok(bounds) => bounds
+            };
+            let size = (end - start) as f64 + 1.0;
+
+            let mut locations: Vec<(Location, f64)> = Vec::new();
+            let mut covered: u64 = 0;
+            let mut covered_until: Option<u32> = None;
+            for range in self.look_up_interval(start, end) {
+                let (first, last) = (range.start.max(start), range.end.min(end));
+                let addresses = (last - first) as u64 + 1;
+                let location = range.location();
+                match locations.iter_mut().find(|(known, _)| *known == location) {
+                    Some((_, count)) => *count += addresses as f64,
+                    None => locations.push((location, addresses as f64)),
+                }
+
+                // Overlapping ranges only count once towards the covered part
+                let first = match covered_until {
+                    Some(until) if last <= until => continue,
+                    Some(until) if first <= until => until + 1,
+                    _ => first,
+                };
+                covered += (last - first) as u64 + 1;
+                covered_until = Some(last);
+            }
+
+            for (_, count) in locations.iter_mut() {
+                *count /= size;
+            }
+            locations.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+            Some(BlockSummary { start, end, locations, unassigned: 1.0 - covered as f64 / size })
+        }
+
+        // The range containing ip, or else the nearest range at most tolerance addresses away
+        // from it. When the ranges on both sides are equally near, the one below ip wins.
+        pub fn look_up_tolerant(&self, ip: &Ipv4Addr, tolerance: u32) -> Option<&IpRange> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            if let Some(range) = self.look_up_u32(ip_decimal) {
+                return Some(range);
+            }
+
+            let next = self.ip_ranges.partition_point(|range| range.start <= ip_decimal);
+            let below = next.checked_sub(1)
+                .map(|index| &self.ip_ranges[index])
+                .map(|range| (ip_decimal.saturating_sub(range.end), range));
+            let above = self.ip_ranges.get(next).map(|range| (range.start - ip_decimal, range));
+
+            let nearest = match (below, above) {
+                (Some(below), Some(above)) if above.0 < below.0 => Some(above),
+                (Some(below), _) => Some(below),
+                (None, above) => above,
+            };
+            nearest.filter(|(distance, _)| *distance <= tolerance).map(|(_, range)| range)
+        }
+
+        // Ranges whose start and end are in different blocks of this prefix length, like a range
+        // from 9.255.255.0 to 10.0.0.255 for 8. Often a sign of rows aggregated by mistake.
+        pub fn ranges_crossing_prefix(&self, prefix_len: u8) -> Vec<&IpRange> {
+            let shift = 32 - prefix_len.min(32) as u32;
+            let block = |address: u32| address.checked_shr(shift).unwrap_or(0);
+            self.ip_ranges.iter()
+                .filter(|range| block(range.start) != block(range.end))
+                .collect()
+        }
+
+        pub fn health(&self) -> Health {
+            let non_overlapping = self.ip_ranges.windows(2).all(|pair| pair[0].end < pair[1].start);
+            let covered_addresses = self.covered_addresses();
+
+            Health {
+                row_count: self.ip_ranges.len(),
+                non_empty: !self.ip_ranges.is_empty(),
+                non_overlapping,
+                covered_addresses,
+                coverage: covered_addresses as f64 / IPV4_ADDRESS_COUNT as f64,
+            }
+        }
+
+        // Number of distinct addresses covered by at least one range
+        fn covered_addresses(&self) -> u64 {
+            count_covered(self.ip_ranges.iter())
+        }
+
+        // Share of the 2^32 IPv4 addresses covered by at least one range, from 0.0 to 1.0. Overlapping
+        // ranges are counted once, and the count is a u64, so a database covering everything is exactly 1.0.
+        pub fn coverage_fraction(&self) -> f64 {
+            self.covered_addresses() as f64 / IPV4_ADDRESS_COUNT as f64
+        }
+
+        // Number of distinct addresses in the ranges of this country, matched case-insensitively.
+        // Addresses in overlapping ranges are counted once, an unknown country has 0.
+        pub fn address_count_for_country(&self, country: &str) -> u64 {
+            count_covered(self.ip_ranges.iter().filter(|range| eq_ignore_case(&range.country, country)))
+        }
+
+        // Address counts of the narrowest and widest range, None for an empty index. A u64 like
+        // IpRange::address_count, since a range covering everything holds 2^32 addresses.
+        pub fn min_range_size(&self) -> Option<u64> {
+            self.ip_ranges.iter().map(IpRange::address_count).min()
+        }
+
+        pub fn max_range_size(&self) -> Option<u64> {
+            self.ip_ranges.iter().map(IpRange::address_count).max()
+        }
+
+        // The inclusive intervals of addresses covered only by self and only by other, however
+        // the rows are split. Where both cover an address the locations aren't compared, see diff.
+        pub fn coverage_diff(&self, other: &RangeIndex) -> CoverageDiff {
+            let (ours, theirs) = (coverage_intervals(&self.ip_ranges), coverage_intervals(&other.ip_ranges));
+            CoverageDiff {
+                only_in_self: subtract_intervals(&ours, &theirs),
+                only_in_other: subtract_intervals(&theirs, &ours),
+            }
+        }
+
+        // Ranges only in other are added, ranges only in self are removed
+        pub fn diff(&self, other: &RangeIndex) -> DatabaseDiff {
+            let mut diff = DatabaseDiff::default();
+            let (mut old, mut new) = (self.ip_ranges.iter().peekable(), other.ip_ranges.iter().peekable());
+
+            loop {
+                match (old.peek(), new.peek()) {
+                    (Some(old_range), Some(new_range)) => {
+                        match (old_range.start, old_range.end).cmp(&(new_range.start, new_range.end)) {
+                            Ordering::Less => diff.removed.push(old.next().unwrap().clone()),
+                            Ordering::Greater => diff.added.push(new.next().unwrap().clone()),
+                            Ordering::Equal => {
+                                let (old_range, new_range) = (old.next().unwrap(), new.next().unwrap());
+                                if !old_range.same_location(new_range) {
+                                    diff.changed.push((old_range.clone(), new_range.clone()));
+                                }
+                            }
+                        }
+                    },
+                    (Some(_), None) => diff.removed.push(old.next().unwrap().clone()),
+                    (None, Some(_)) => diff.added.push(new.next().unwrap().clone()),
+                    (None, None) => break,
+                }
+            }
+
+            diff
+        }
+
+        fn find(&self, ip: &Ipv4Addr) -> Option<&IpRange> {
+            let ip_decimal = ip_to_decimal(ip).ok()?;
+            self.look_up_u32(ip_decimal)
+        }
+
+        fn find_index(&self, ip: u32) -> Option<usize> {
+            match &self.prefix_index {
+                Some(prefix_index) => {
+                    let (first, last) = prefix_index.windows[(ip >> 16) as usize];
+                    let (first, last) = (first as usize, last as usize);
+                    find_ip_range_index(ip, &self.ip_ranges[first..last]).map(|index| first + index)
+                },
+                None => find_ip_range_index(ip, &self.ip_ranges[..]),
+            }
+        }
+
+    }
+
+    impl FileState {
+        fn read(file_path: &Path) -> Option<Self> {
+            let metadata = std::fs::metadata(file_path).ok()?;
+            Some(FileState {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+            })
+        }
+    }
+
+    impl PrefixIndex {
+        // max_end as in RangeIndex, so a window also starts early enough for a long range that
+        // begins before shorter ones and still reaches into the block
+        fn build(ranges: &[IpRange], max_end: &[u32]) -> Self {
+            let windows = (0..=u16::MAX as u32).map(|prefix| {
+                let block_start = prefix << 16;
+                let block_end = block_start | 0xFFFF;
+                let first = max_end.partition_point(|max_end| *max_end < block_start);
+                let last = ranges.partition_point(|range| range.start <= block_end);
+                (first as u32, last.max(first) as u32)
+            }).collect();
+
+            PrefixIndex { windows }
+        }
+    }
+
+    impl From<SortedRanges> for RangeIndex {
+        fn from(ip_ranges: SortedRanges) -> Self {
+            let mut index = RangeIndex { ip_ranges, prefix_index: None, max_end: Arc::new([]), symbols: None };
+            index.rebuild_indexes();
+            index
+        }
+    }
+
+    impl FromIterator<IpRange> for RangeIndex {
+        fn from_iter<I: IntoIterator<Item = IpRange>>(iter: I) -> Self {
+            RangeIndex::from(iter.into_iter().collect::<SortedRanges>())
+        }
+    }
+
+    impl Extend<IpRange> for RangeIndex {
+        fn extend<I: IntoIterator<Item = IpRange>>(&mut self, iter: I) {
+            self.ip_ranges.extend(iter);
+            self.rebuild_indexes();
+        }
+    }
+
+    impl<S: RangeStore> Deref for Looker<S> {
+        type Target = S;
+
+        fn deref(&self) -> &S {
+            &self.index
+        }
+    }
+
+    impl RangeStore for RangeIndex {
+        fn search(&self, ip: u32) -> Option<usize> {
+            self.find_index(ip)
+        }
+
+        fn get(&self, index: usize) -> &IpRange {
+            &self.ip_ranges[index]
+        }
+    }
+
+    impl FromIterator<IpRange> for Looker {
+        fn from_iter<I: IntoIterator<Item = IpRange>>(iter: I) -> Self {
+            Looker::from_index(iter.into_iter().collect())
+        }
+    }
+
+    impl Extend<IpRange> for Looker {
+        fn extend<I: IntoIterator<Item = IpRange>>(&mut self, iter: I) {
+            self.index.extend(iter);
+        }
+    }
+
+    impl LookerBuilder {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn file_path(mut self, path: PathBuf) -> Self {
+            self.file_path = Some(path);
+            self
+        }
+
+        pub fn allowed_countries(mut self, countries: Vec<String>) -> Self {
+            if countries.is_empty() {
+                log::warn!("Allowed countries is empty, filter will be ignored!");
+                self.options.allowed_countries = None;
+                return self;
+            }
+
+            self.options.allowed_countries = Some(countries);
+            self
+        }
+
+        pub fn keep_raw(mut self, keep_raw: bool) -> Self {
+            self.options.keep_raw = keep_raw;
+            self
+        }
+
+        // For csv files with a single CIDR like 1.0.0.0/24 per row instead of a start and end
+        // column. The country, region and city are then read from the three following columns.
+        pub fn cidr_column(mut self, column: usize) -> Self {
+            self.options.bounds = BoundsColumns::Cidr(column);
+            self.options.location_columns = (column + 1, column + 2, column + 3);
+            self
+        }
+
+        // For csv files with the range as a single column like 1.0.0.0-1.0.0.255. The country,
+        // region and city are then read from the three following columns.
+        pub fn range_column(mut self, column: usize) -> Self {
+            self.options.bounds = BoundsColumns::Dashed(column);
+            self.options.location_columns = (column + 1, column + 2, column + 3);
+            self
+        }
+
+        // For csv files with the number of addresses in a range instead of its end, in this
+        // column. The end is start + count - 1, a count of 0 or one reaching past
+        // 255.255.255.255 fails the load with the row.
+        pub fn length_column(mut self, column: usize) -> Self {
+            let start = match self.options.bounds {
+                BoundsColumns::StartEnd(start, _) | BoundsColumns::StartCount(start, _) => start,
+                _ => 0,
+            };
+            self.options.bounds = BoundsColumns::StartCount(start, column);
+            self
+        }
+
+        // For a start and end column where end is exclusive. The ends are made inclusive while
+        // loading, so 0,256 is stored as 0 to 255 and an end of 4294967296 covers the last
+        // address. CIDR and dashed ranges are always inclusive.
+        pub fn end_bound(mut self, end_bound: EndBound) -> Self {
+            self.options.end_bound = end_bound;
+            self
+        }
+
+        pub fn location_columns(mut self, country: usize, region: usize, city: usize) -> Self {
+            self.options.location_columns = (country, region, city);
+            self
+        }
+
+        // Replaces invalid UTF-8 in the country, region and city columns instead of failing
+        // the load, useful for files with latin-1 names. The start and end are still strict.
+        pub fn lossy_utf8(mut self, lossy: bool) -> Self {
+            self.options.lossy_utf8 = lossy;
+            self
+        }
+
+        // Strips whitespace and ' and " characters from both ends of every text field while
+        // loading, for messy exports with values like " Sweden" or "'Stockholm'". Off by default
+        // so values are kept exactly as in the file.
+        pub fn trim_fields(mut self, trim: bool) -> Self {
+            self.options.trim_fields = trim;
+            self
+        }
+
+        // The byte separating columns, a comma by default. For example b';' or b'\t'.
+        pub fn delimiter(mut self, delimiter: u8) -> Self {
+            self.options.delimiter = delimiter;
+            self
+        }
+
+        // Skips lines starting with this byte, like Some(b'#') for annotated files. Off by
+        // default. Empty lines are always skipped.
+        pub fn comment(mut self, comment: Option<u8>) -> Self {
+            self.options.comment = comment;
+            self
+        }
+
+        // Whether the first row of the csv is a header to skip, which is the default.
+        // Set to false for headerless files, otherwise their first range is lost.
+        pub fn has_headers(mut self, has_headers: bool) -> Self {
+            self.options.has_headers = has_headers;
+            self
+        }
+
+        // Tags every loaded range with the name of this database, see IpRange::source
+        pub fn source(mut self, source: &str) -> Self {
+            self.options.source = Some(source.into());
+            self
+        }
+
+        // Reads the organization or netname of each range from this column, empty values are left as None
+        pub fn org_column(mut self, column: usize) -> Self {
+            self.options.org_column = Some(column);
+            self
+        }
+
+        // Reads the full country name of each range from this column, empty values are left as None
+        pub fn country_name_column(mut self, column: usize) -> Self {
+            self.options.country_name_column = Some(column);
+            self
+        }
+
+        // Reads the region code of each range from this column, see IpRange::region_code.
+        // The region column keeps the name. Empty values are left as None.
+        pub fn region_code_column(mut self, column: usize) -> Self {
+            self.options.region_code_column = Some(column);
+            self
+        }
+
+        // Reads whether each range is anycast from this column, see IpRange::is_anycast. 1, true,
+        // yes, t and y in any case mean anycast, 0, false, no, f, n and an empty value don't, and
+        // anything else fails the row.
+        pub fn anycast_column(mut self, column: usize) -> Self {
+            self.options.anycast_column = Some(column);
+            self
+        }
+
+        // Stores the value of this column under key, see IpRange::attribute. Can be called for
+        // as many columns as needed, empty values are left out.
+        pub fn attribute_column(mut self, key: &str, column: usize) -> Self {
+            self.options.attribute_columns.push((key.to_string(), column));
+            self
+        }
+
+        // Reads latitude and longitude from these columns, empty values are left as None
+        pub fn coordinate_columns(mut self, latitude: usize, longitude: usize) -> Self {
+            self.options.coordinate_columns = Some((latitude, longitude));
+            self
+        }
+
+        // For databases with several weighted locations per range. The weight of the location in
+        // the location columns is read from weight_column, and the rest of the row after it is
+        // read as groups of country, region, city and weight. Groups with an empty country
+        // are skipped. The highest weighted location becomes the country, region and city of
+        // the range, see Looker::look_up_candidates.
+        pub fn candidate_columns(mut self, weight_column: usize) -> Self {
+            self.options.candidate_columns = Some(weight_column);
+            self
+        }
+
+        // The range table is allocated up front for the file size divided by this, 48 bytes by
+        // default, which fits locationv4.csv. Set it closer to the real row length for files with
+        // much longer or shorter rows to avoid growing the table, or overshooting it, while loading.
+        // Ignored with allowed_countries, only_within or a dropping max_range_size, where the
+        // table starts empty since most rows may be left out.
+        pub fn average_row_length(mut self, bytes: u64) -> Self {
+            self.options.average_row_length = bytes;
+            self
+        }
+
+        // Fails the load with LookerError::TooManyRows once the file has more than max data rows,
+        // before they use up memory. A guard against loading the wrong file. Unbounded by default.
+        pub fn max_rows(mut self, max: usize) -> Self {
+            self.options.max_rows = Some(max);
+            self
+        }
+
+        // Ranges covering more than size addresses are dropped while loading, or only keep
+        // their country with keep_coarse_countries. How many is in Looker::load_stats.
+        pub fn max_range_size(mut self, size: u32) -> Self {
+            self.options.max_range_size = Some(size as u64);
+            self
+        }
+
+        // Same as max_range_size, with the size of a block of this prefix length. min_prefix(16)
+        // filters out every range larger than a /16.
+        pub fn min_prefix(mut self, prefix_len: u8) -> Self {
+            self.options.max_range_size = Some(1u64 << (32 - prefix_len.min(32) as u32));
+            self
+        }
+
+        // Fails the load with LookerError::EmptyField on the first row with an empty or
+        // whitespace-only country, instead of loading a range that matches nowhere useful
+        pub fn require_country(mut self, require: bool) -> Self {
+            self.options.required_fields.0 = require;
+            self
+        }
+
+        // Same as require_country for the region and city, ignored with FieldSet::CountryOnly
+        pub fn require_region(mut self, require: bool) -> Self {
+            self.options.required_fields.1 = require;
+            self
+        }
+
+        pub fn require_city(mut self, require: bool) -> Self {
+            self.options.required_fields.2 = require;
+            self
+        }
+
+        // Stores placeholder, like "Unknown", instead of an empty or whitespace-only country,
+        // region or city. Off by default, so empty fields stay empty. Applied after
+        // normalize_countries and before allowed_countries, and never to the region and city
+        // with FieldSet::CountryOnly. Note that IpRange::precision sees the placeholder as filled in.
+        pub fn empty_as(self, placeholder: &str) -> Self {
+            self.empty_country_as(placeholder)
+                .empty_region_as(placeholder)
+                .empty_city_as(placeholder)
+        }
+
+        pub fn empty_country_as(mut self, placeholder: &str) -> Self {
+            self.options.empty_placeholders.0 = Some(placeholder.to_string());
+            self
+        }
+
+        pub fn empty_region_as(mut self, placeholder: &str) -> Self {
+            self.options.empty_placeholders.1 = Some(placeholder.to_string());
+            self
+        }
+
+        pub fn empty_city_as(mut self, placeholder: &str) -> Self {
+            self.options.empty_placeholders.2 = Some(placeholder.to_string());
+            self
+        }
+
+        // Only loads the part of the database inside the inclusive interval [start, end]. Rows
+        // crossing its edges are cut down to it, so lookups outside it always miss, see
+        // Looker::in_scope. How many rows were kept is in Looker::load_stats.
+        pub fn only_within(mut self, start: u32, end: u32) -> Self {
+            self.options.only_within = Some((start.min(end), start.max(end)));
+            self
+        }
+
+        // Drops rows with the same bounds, country, region and city as the row before them once
+        // the ranges are sorted. How many is in Looker::load_stats.
+        pub fn dedup(mut self, dedup: bool) -> Self {
+            self.options.dedup = dedup;
+            self
+        }
+
+        pub fn keep_coarse_countries(mut self, keep: bool) -> Self {
+            self.options.keep_coarse_countries = keep;
+            self
+        }
+
+        pub fn fields(mut self, fields: FieldSet) -> Self {
+            self.options.fields = fields;
+            self
+        }
+
+        // Rewrites countries to ISO 3166-1 alpha-2 codes while loading. This happens before
+        // the allowed countries filter, so the filter should then be given alpha-2 codes.
+        pub fn normalize_countries(mut self, normalize: bool) -> Self {
+            self.options.normalize_countries = normalize;
+            self
+        }
+
+        // Builds a /16 prefix index over the ranges for faster lookups, see Looker::with_trie
+        pub fn with_trie(mut self) -> Self {
+            self.with_trie = true;
+            self
+        }
+
+        // Interns the location strings into symbol ids while building, see RangeIndex::with_symbols
+        pub fn intern_symbols(mut self, intern: bool) -> Self {
+            self.intern_symbols = intern;
+            self
+        }
+
+        // Makes look_up_detailed return LookupError::Reserved for private, loopback and other
+        // special-purpose addresses without searching. Off by default, so databases that map
+        // internal ranges like 10.0.0.0/8 on purpose keep answering for them.
+        pub fn skip_reserved(mut self, skip_reserved: bool) -> Self {
+            self.skip_reserved = skip_reserved;
+            self
+        }
+
+        // Edition or release date of the database, like "2024-06", returned next to every match
+        // by Looker::look_up_with_meta so results tell which data they came from
+        pub fn version(mut self, version: &str) -> Self {
+            self.version = Some(version.into());
+            self
+        }
+
+        pub fn reject_empty(mut self, reject_empty: bool) -> Self {
+            self.reject_empty = reject_empty;
+            self
+        }
+
+        pub fn build(self) -> Result<Looker, Box<dyn Error>> {
+            if self.reject_empty {
+                return Ok(self.try_build()?);
+            }
+
+            let file_path = self.file_path.ok_or(LookerError::MissingFilePath)?;
+            let path = file_path.to_str().ok_or_else(|| LookerError::InvalidFilePath(file_path.clone()))?;
+            let file_state = FileState::read(&file_path);
+            let (ip_ranges, load_stats) = match read_ip_ranges(path, &self.options) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    log::error!("Error reading IP ranges: {}", e);
+                    (SortedRanges::default(), LoadStats::default())
+                }
+            };
+
+            if ip_ranges.is_empty() {
+                log::warn!("No IP ranges loaded, every lookup will return None!");
+            }
+
+            let mut looker = Looker {
+                file_path,
+                index: RangeIndex::from(ip_ranges),
+                options: self.options,
+                file_state,
+                format: SourceFormat::Csv,
+                load_stats,
+                skip_reserved: self.skip_reserved,
+                version: self.version.clone(),
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            };
+
+            if self.with_trie {
+                looker = looker.with_trie();
+            }
+            if self.intern_symbols {
+                looker = looker.with_symbols();
+            }
+            Ok(looker)
+        }
+
+        // Converts the csv to the compact binary format read by Looker::from_binary, one row at
+        // a time. Peak memory is a single row and the writer's buffer, whatever the size of the
+        // csv, plus the cache of normalized countries when normalize_countries is on. Options
+        // needing the whole table, like dedup, are skipped, and only the bounds and location
+        // are written. Returns what happened to the rows.
+        pub fn write_binary<W: std::io::Write>(self, writer: W) -> Result<LoadStats, LookerError> {
+            let file_path = self.file_path.ok_or(LookerError::MissingFilePath)?;
+            let file = std::fs::File::open(&file_path).map_err(|e| LookerError::Csv(e.into()))?;
+            if self.options.dedup {
+                log::warn!("dedup needs the whole table and is skipped when writing a binary database");
+            }
+
+            let mut writer = std::io::BufWriter::new(writer);
+            crate::binary::write_header(&mut writer)?;
+            let mut stats = LoadStats::default();
+            for_each_csv_range(file, &self.options, &mut stats, |range| {
+                crate::binary::write_range(&mut writer, &range).map_err(LookerError::Io)
+            })?;
+            std::io::Write::flush(&mut writer)?;
+            Ok(stats)
+        }
+
+        // Parses the file with these options and hands every range to f as soon as its row is
+        // read, without keeping them, for custom destinations and one-pass transformations.
+        // Stops at the first error, from loading (converted with From) or returned by f.
+        // dedup is ignored since it needs every range. Rows come in file order, unsorted.
+        pub fn for_each_range<E: From<LookerError>>(self, mut f: impl FnMut(IpRange) -> Result<(), E>) -> Result<LoadStats, E> {
+            let file_path = self.file_path.ok_or(LookerError::MissingFilePath)?;
+            let file = std::fs::File::open(&file_path).map_err(|e| LookerError::Csv(e.into()))?;
+            if self.options.dedup {
+                log::warn!("dedup needs the whole table and is skipped when streaming ranges");
+            }
+
+            // for_each_csv_range only knows LookerError, so an error from f is kept aside and
+            // loading is stopped with a placeholder that is never returned
+            let mut callback_error = None;
+            let mut stats = LoadStats::default();
+            let result = for_each_csv_range(file, &self.options, &mut stats, |range| {
+                f(range).map_err(|e| {
+                    callback_error = Some(e);
+                    LookerError::Io(std::io::Error::other("stopped by the callback"))
+                })
+            });
+            if let Some(e) = callback_error {
+                return Err(e);
+            }
+            result?;
+            Ok(stats)
+        }
+
+        // Unlike build, errors while reading the file are returned instead of logged
+        pub fn try_build(self) -> Result<Looker, LookerError> {
+            let file_path = self.file_path.ok_or(LookerError::MissingFilePath)?;
+            let path = file_path.to_str().ok_or_else(|| LookerError::InvalidFilePath(file_path.clone()))?;
+            let file_state = FileState::read(&file_path);
+            let (ip_ranges, load_stats) = read_ip_ranges(path, &self.options)?;
+
+            if ip_ranges.is_empty() {
+                if self.reject_empty {
+                    return Err(LookerError::EmptyDatabase(file_path));
+                }
+                log::warn!("No IP ranges loaded, every lookup will return None!");
+            }
+
+            let mut looker = Looker {
+                file_path,
+                index: RangeIndex::from(ip_ranges),
+                options: self.options,
+                file_state,
+                format: SourceFormat::Csv,
+                load_stats,
+                skip_reserved: self.skip_reserved,
+                version: self.version.clone(),
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            };
+
+            if self.with_trie {
+                looker = looker.with_trie();
+            }
+            if self.intern_symbols {
+                looker = looker.with_symbols();
+            }
+            Ok(looker)
+        }
+    }
+
+    const IPV4_ADDRESS_COUNT: u64 = 1 << 32;
+
+    pub(crate) const UNKNOWN_COUNTRY: &str = "unknown";
+
+    // Rough size of a row in the ip csv, used to pre-size the range table from the file size
+    const AVERAGE_ROW_LENGTH: u64 = 48;
+
+    // Limits on pre-sizing from a zip entry, see Looker::from_zip
+    #[cfg(feature = "zip")]
+    const MAX_ZIP_RATIO: u64 = 20;
+    #[cfg(feature = "zip")]
+    const MAX_ZIP_ROW_ESTIMATE: usize = 4 << 20;
+
+    fn estimate_row_count(file_path: &str, average_row_length: u64) -> usize {
+        match std::fs::metadata(file_path) {
+            Ok(metadata) => (metadata.len() / average_row_length.max(1)) as usize,
+            Err(_) => 0,
+        }
+    }
+
+    // The ranges of a binary database, see Looker::from_binary
+    fn read_fixed_width_ranges(file_path: &Path, schema: &FixedWidthSchema) -> Result<SortedRanges, LookerError> {
+        use std::io::BufRead;
+
+        let file = std::fs::File::open(file_path)?;
+        let mut ip_ranges = Vec::new();
+
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = index as u64 + 1;
+            let column = |range: &Range<usize>, name: &'static str| {
+                line.get(range.clone()).map(str::trim).ok_or_else(|| LookerError::Parse {
+                    row,
+                    column: name,
+                    value: line.clone(),
+                    expected: "a column within the line",
+                })
+            };
+
+            let start = column(&schema.start, "start")?;
+            let end = column(&schema.end, "end")?;
+            ip_ranges.push(IpRange::new(
+                parse_field(start, row, "start", "u32", |value| value.parse().ok())?,
+                parse_field(end, row, "end", "u32", |value| value.parse().ok())?,
+                column(&schema.country, "country")?.to_string(),
+                column(&schema.region, "region")?.to_string(),
+                column(&schema.city, "city")?.to_string(),
+            ));
+        }
+        Ok(SortedRanges::from_unsorted(ip_ranges))
+    }
+
+    fn read_binary_ranges(file_path: &Path, format: &SourceFormat) -> Result<SortedRanges, LookerError> {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(file_path)?);
+        // Dumps in network order have no header
+        let order = match format {
+            SourceFormat::NetworkOrderBinary => crate::binary::ByteOrder::Big,
+            _ => {
+                crate::binary::read_header(&mut reader)?;
+                crate::binary::ByteOrder::Little
+            }
+        };
+
+        let mut ip_ranges = Vec::new();
+        while let Some(range) = crate::binary::read_range(&mut reader, order)? {
+            ip_ranges.push(range);
+        }
+        Ok(SortedRanges::from_unsorted(ip_ranges))
+    }
+
+    fn read_ip_ranges(file_path: &str, options: &LoadOptions) -> Result<(SortedRanges, LoadStats), LookerError> {
+        let file = std::fs::File::open(file_path).map_err(|e| LookerError::Csv(e.into()))?;
+        read_ip_ranges_from(file, estimate_row_count(file_path, options.average_row_length), options)
+    }
+
+    fn read_ip_ranges_from<R: std::io::Read>(reader: R, row_estimate: usize, options: &LoadOptions) -> Result<(SortedRanges, LoadStats), LookerError> {
+        // Filters can drop most of the rows, so only pre-size when every row is kept. Never reserve
+        // more than max_rows, the estimate for a huge wrong file could exhaust memory by itself.
+        let filtered = options.allowed_countries.as_ref().is_some_and(|filter| !filter.is_empty())
+            || options.only_within.is_some()
+            || (options.max_range_size.is_some() && !options.keep_coarse_countries);
+        let row_estimate = if filtered { 0 } else { row_estimate };
+        let mut ip_ranges = Vec::with_capacity(options.max_rows.map_or(row_estimate, |max| row_estimate.min(max)));
+        let mut stats = LoadStats::default();
+        for_each_csv_range(reader, options, &mut stats, |range| {
+            ip_ranges.push(range);
+            Ok(())
+        })?;
+
+        // Lookups binary search by start, so a file in any other order (by end, say) is
+        // sorted here rather than giving wrong answers
+        if !ip_ranges.is_sorted_by_key(|range| range.start) {
+            log::warn!("IP ranges are not sorted by start in the file, sorting them");
+        }
+
+        if options.dedup {
+            let count = ip_ranges.len();
+            ip_ranges.sort_by_key(|range| (range.start, range.end));
+            ip_ranges.dedup_by(|range, previous| (range.start, range.end) == (previous.start, previous.end) && range.same_location(previous));
+            stats.duplicates_removed = count - ip_ranges.len();
+            if stats.duplicates_removed > 0 {
+                log::info!("Removed {} duplicate IP ranges", stats.duplicates_removed);
+            }
+        }
+
+        // from_unsorted copies the ranges into a shared slice, so while it runs both are in
+        // memory. Shrinking first keeps an overshooting estimate out of that peak.
+        ip_ranges.shrink_to_fit();
+        let ip_ranges = SortedRanges::from_unsorted(ip_ranges);
+        stats.rows_kept = ip_ranges.len();
+        Ok((ip_ranges, stats))
+    }
+
+    // Parses the csv one row at a time, handing every range that passes the options to
+    // handle in file order. Only one row is held at a time.
+    fn for_each_csv_range<R: std::io::Read>(reader: R, options: &LoadOptions, stats: &mut LoadStats, mut handle: impl FnMut(IpRange) -> Result<(), LookerError>) -> Result<(), LookerError> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(options.has_headers)
+            .delimiter(options.delimiter)
+            .comment(options.comment)
+            .from_reader(reader);
+
+        let allowed_countries = match options.allowed_countries.as_ref() {
+            Some(filter) => {
+                if filter.is_empty() {
+                    log::warn!("Country filter is empty, filter will be ignored!");
+                    None
+                } else {
+                    Some(filter)
+                }
+            },
+            None => None,
+        };
+
+        let mut normalized_countries: HashMap<String, String> = HashMap::new();
+        let required_columns = options.required_columns();
+
+        // Reuse one record buffer for the whole file instead of allocating a record per row
+        let mut record = ByteRecord::new();
+        while rdr.read_byte_record(&mut record).map_err(csv_error)? {
+            let row = record.position().map_or(0, |position| position.line());
+            stats.rows_read += 1;
+            if let Some(max) = options.max_rows {
+                if stats.rows_read > max {
+                    return Err(LookerError::TooManyRows { max, row });
+                }
+            }
+            if record.len() < required_columns {
+                return Err(LookerError::WrongColumnCount { row, found: record.len(), expected: required_columns });
+            }
+            let field = |index: usize, column: &'static str| -> Result<Cow<str>, LookerError> {
+                let value = text_field(&record, index, row, column, options.lossy_utf8)?;
+                Ok(match value {
+                    Cow::Borrowed(value) if options.trim_fields => Cow::Borrowed(trim_field(value)),
+                    Cow::Owned(value) if options.trim_fields => Cow::Owned(trim_field(&value).to_string()),
+                    value => value,
+                })
+            };
+            let (mut start, mut end) = match options.bounds {
+                BoundsColumns::StartEnd(start, end) => (
+                    parse_field(&strict_field(&record, start, row, "start")?, row, "start", "u32", |value| value.parse().ok())?,
+                    match options.end_bound {
+                        EndBound::Inclusive => parse_field(&strict_field(&record, end, row, "end")?, row, "end", "u32", |value| value.parse().ok())?,
+                        EndBound::Exclusive => parse_field(&strict_field(&record, end, row, "end")?, row, "end", "an exclusive end from 1 to 4294967296", |value| {
+                            value.parse::<u64>().ok()?.checked_sub(1).and_then(|end| u32::try_from(end).ok())
+                        })?,
+                    },
+                ),
+                BoundsColumns::StartCount(start, count) => {
+                    let start: u32 = parse_field(&strict_field(&record, start, row, "start")?, row, "start", "u32", |value| value.parse().ok())?;
+                    let end = parse_field(&strict_field(&record, count, row, "count")?, row, "count", "a count from 1 that ends within 255.255.255.255", |value| {
+                        let count: u64 = value.parse().ok()?;
+                        let end = (start as u64).checked_add(count)?.checked_sub(1)?;
+                        // A count of 0 would end before the start
+                        u32::try_from(end).ok().filter(|_| count > 0)
+                    })?;
+                    (start, end)
+                },
+                BoundsColumns::Cidr(column) => parse_field(&strict_field(&record, column, row, "cidr")?, row, "cidr", "a CIDR", |value| parse_cidr(value).ok())?,
+                BoundsColumns::Dashed(column) => parse_field(&strict_field(&record, column, row, "range")?, row, "range", "a range like 1.0.0.0-1.0.0.255", |value| parse_dashed_range(value).ok())?,
+            };
+            if start > end {
+                stats.reversed += 1;
+                continue;
+            }
+            // Coarseness is judged on the full range, before it is cut down to the interval
+            let size = end as u64 + 1 - start as u64;
+            if let Some((scope_start, scope_end)) = options.only_within {
+                if end < scope_start || start > scope_end {
+                    stats.out_of_scope += 1;
+                    continue;
+                }
+                start = start.max(scope_start);
+                end = end.min(scope_end);
+            }
+
+            let read_location = |(country_column, region_column, city_column): (usize, usize, usize)| -> Result<Location, LookerError> {
+                let country = field(country_column, "country")?.into_owned();
+                let (region, city) = match options.fields {
+                    FieldSet::All => (field(region_column, "region")?.into_owned(), field(city_column, "city")?.into_owned()),
+                    FieldSet::CountryOnly => (String::new(), String::new()),
+                };
+                Ok(Location { country, region, city })
+            };
+
+            let mut location = read_location(options.location_columns)?;
+            let mut candidates = Vec::new();
+            if let Some(weight_column) = options.candidate_columns {
+                candidates.push((location, parse_weight(&strict_field(&record, weight_column, row, "weight")?, row)?));
+                let mut column = weight_column + 1;
+                while column + 4 <= record.len() {
+                    let alternative = read_location((column, column + 1, column + 2))?;
+                    if !alternative.country.is_empty() {
+                        candidates.push((alternative, parse_weight(&strict_field(&record, column + 3, row, "weight")?, row)?));
+                    }
+                    column += 4;
+                }
+
+                // Stable, so the primary location stays first on a tie
+                candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+                location = candidates[0].0.clone();
+                if candidates.len() == 1 {
+                    candidates.clear();
+                }
+            }
+            let Location { mut country, mut region, mut city } = location;
+
+            let (require_country, require_region, require_city) = options.required_fields;
+            let checks = [
+                (require_country, &country, "country"),
+                (require_region && options.fields == FieldSet::All, &region, "region"),
+                (require_city && options.fields == FieldSet::All, &city, "city"),
+            ];
+            for (required, value, column) in checks {
+                if required && value.trim().is_empty() {
+                    return Err(LookerError::EmptyField { row, column });
+                }
+            }
+
+            if let Some(max_range_size) = options.max_range_size {
+                if size > max_range_size {
+                    if !options.keep_coarse_countries {
+                        stats.coarse_dropped += 1;
+                        continue;
+                    }
+                    stats.coarse_downgraded += 1;
+                    region.clear();
+                    city.clear();
+                    candidates.clear();
+                }
+            }
+
+            let (latitude, longitude) = match options.coordinate_columns {
+                Some((latitude_column, longitude_column)) => (
+                    parse_coordinate(&strict_field(&record, latitude_column, row, "latitude")?, row, "latitude")?,
+                    parse_coordinate(&strict_field(&record, longitude_column, row, "longitude")?, row, "longitude")?,
+                ),
+                None => (None, None),
+            };
+
+            let org = match options.org_column {
+                Some(org_column) => Some(field(org_column, "org")?.trim().to_string()).filter(|org| !org.is_empty()),
+                None => None,
+            };
+
+            let country_name = match options.country_name_column {
+                Some(country_name_column) => Some(field(country_name_column, "country_name")?.trim().to_string()).filter(|name| !name.is_empty()),
+                None => None,
+            };
+
+            let region_code = match options.region_code_column {
+                Some(region_code_column) => Some(field(region_code_column, "region_code")?.trim().to_string()).filter(|code| !code.is_empty()),
+                None => None,
+            };
+
+            let anycast = match options.anycast_column {
+                Some(anycast_column) => parse_flag(&field(anycast_column, "anycast")?, row, "anycast")?,
+                None => false,
+            };
+
+            let mut attributes = HashMap::new();
+            for (key, column) in &options.attribute_columns {
+                let value = field(*column, "attribute")?;
+                if !value.trim().is_empty() {
+                    attributes.insert(key.clone(), value.trim().to_string());
+                }
+            }
+
+            if options.normalize_countries {
+                country = normalized_countries.entry(country).or_insert_with_key(|country| {
+                    match normalize_country(country) {
+                        Some(code) => code.to_string(),
+                        None => {
+                            log::warn!("Unrecognized country '{}', keeping it as is", country);
+                            country.clone()
+                        }
+                    }
+                }).clone();
+            }
+
+            let (country_placeholder, region_placeholder, city_placeholder) = &options.empty_placeholders;
+            let placeholders = [
+                (country_placeholder.as_ref(), &mut country),
+                (region_placeholder.as_ref().filter(|_| options.fields == FieldSet::All), &mut region),
+                (city_placeholder.as_ref().filter(|_| options.fields == FieldSet::All), &mut city),
+            ];
+            for (placeholder, value) in placeholders {
+                if let Some(placeholder) = placeholder {
+                    if value.trim().is_empty() {
+                        value.clone_from(placeholder);
+                    }
+                }
+            }
+
+            if let Some(filter) = allowed_countries {
+                if !filter.contains(&country) {
+                    continue;
+                }
+            }
+            
+            let raw = match (options.keep_raw, options.lossy_utf8) {
+                (false, _) => None,
+                (true, true) => Some(StringRecord::from_byte_record_lossy(record.clone())),
+                (true, false) => Some(StringRecord::from_byte_record(record.clone()).map_err(|e| LookerError::Parse {
+                    row,
+                    column: "raw",
+                    value: String::from_utf8_lossy(e.into_byte_record().as_slice()).into_owned(),
+                    expected: "UTF-8",
+                })?),
+            };
+
+            let source = options.source.clone();
+
+            let extras = RangeExtras { org, country_name, region_code, latitude, longitude, raw, candidates, attributes }.boxed();
+            handle(IpRange { start, end, country, region, city, anycast, symbol_ids: None, synthetic: false, source, extras })?;
+            stats.rows_kept += 1;
+        }
+
+        if stats.coarse_dropped > 0 || stats.coarse_downgraded > 0 {
+            log::info!("{} coarse IP ranges dropped and {} reduced to their country", stats.coarse_dropped, stats.coarse_downgraded);
+        }
+
+        if stats.out_of_scope > 0 {
+            log::info!("Skipped {} IP ranges outside the loaded interval", stats.out_of_scope);
+        }
+
+        if stats.reversed > 0 {
+            log::warn!("Skipped {} IP ranges ending before they start", stats.reversed);
+        }
+        Ok(())
+    }
+
+    // The csv reader already rejects rows with a different length than the first one
+    fn csv_error(e: csv::Error) -> LookerError {
+        match e.kind() {
+            csv::ErrorKind::UnequalLengths { pos, expected_len, len } => LookerError::WrongColumnCount {
+                row: pos.as_ref().map_or(0, |position| position.line()),
+                found: *len as usize,
+                expected: *expected_len as usize,
+            },
+            _ => LookerError::Csv(e),
+        }
+    }
+
+    fn find_ip_range(ip: u32, ranges: &[IpRange]) -> Option<IpRange> {
+        find_ip_range_index(ip, ranges).map(|index| ranges[index].clone())
+    }
+
+    // Both ends of a range are inclusive and only compared with < and >, so no arithmetic
+    // can overflow. A range of 0..=u32::MAX matches every address, 0.0.0.0 and
+    // 255.255.255.255 included, and ranges touching either end of the space behave the same.
+    fn find_ip_range_index(ip: u32, ranges: &[IpRange]) -> Option<usize> {
+        find_in_ranges(ip as u64, ranges, |range| (range.start as u64, range.end as u64))
+    }
+
+    // Binary search for the index of the range containing value, for any slice of
+    // non-overlapping ranges sorted by start. bounds gives the inclusive (start, end) of an item.
+    pub fn find_in_ranges<T>(value: u64, ranges: &[T], bounds: impl Fn(&T) -> (u64, u64)) -> Option<usize> {
+        ranges.binary_search_by(|range| {
+            let (start, end) = bounds(range);
+            if value < start {
+                Ordering::Greater // Search the left side
+            } else if value > end {
+                Ordering::Less // Search the right side
+            } else {
+                Ordering::Equal // Value is within this range
+            }
+        }).ok()
+    }
+
+    // Prefix length of the aligned CIDR block of range that contains ip, which must be in range
+    fn prefix_len_at(range: &IpRange, ip: u32) -> u32 {
+        // u64 so the block ending at 255.255.255.255 doesn't overflow
+        let (mut block_start, end, ip) = (range.start as u64, range.end as u64, ip as u64);
+        loop {
+            let alignment = if block_start == 0 { 1 << 32 } else { 1 << block_start.trailing_zeros() };
+            let mut size: u64 = alignment;
+            while block_start + size - 1 > end {
+                size >>= 1;
+            }
+            if ip < block_start + size {
+                return 32 - size.trailing_zeros();
+            }
+            block_start += size;
+        }
+    }
+
+    // The covered addresses of ranges sorted by start as disjoint, non-adjacent intervals
+    fn coverage_intervals(ranges: &[IpRange]) -> Vec<(u32, u32)> {
+        let mut intervals: Vec<(u32, u32)> = Vec::new();
+        for range in ranges {
+            match intervals.last_mut() {
+                Some(last) if range.start as u64 <= last.1 as u64 + 1 => last.1 = last.1.max(range.end),
+                _ => intervals.push((range.start, range.end)),
+            }
+        }
+        intervals
     }
 
-    #[derive(Debug, Default)]
-    pub struct LookerBuilder {
-        file_path: Option<PathBuf>,
-        allowed_countries: Option<Vec<String>>,
+    // The parts of the intervals in from not in any interval of remove, both from coverage_intervals
+    fn subtract_intervals(from: &[(u32, u32)], remove: &[(u32, u32)]) -> Vec<(u32, u32)> {
+        let mut left = Vec::new();
+        let mut removing = remove.iter().peekable();
+        for &(start, end) in from {
+            // u64 so the address after u32::MAX doesn't overflow
+            let mut uncovered_from = start as u64;
+            while let Some(&&(remove_start, remove_end)) = removing.peek() {
+                if remove_start > end {
+                    break;
+                }
+                if remove_start as u64 > uncovered_from {
+                    left.push((uncovered_from as u32, remove_start - 1));
+                }
+                uncovered_from = uncovered_from.max(remove_end as u64 + 1);
+                if remove_end > end {
+                    // Can still overlap the next interval
+                    break;
+                }
+                removing.next();
+            }
+            if uncovered_from <= end as u64 {
+                left.push((uncovered_from as u32, end));
+            }
+        }
+        left
     }
 
-    pub trait IpLookup {
-        fn look_up(&self, ip: &str) -> Option<IpRange>;
-        fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange>;
-    }
+    // Great-circle distance between two latitude, longitude points in degrees
+    fn haversine_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
 
-    impl Looker {
+        let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+        let half_dlat = (to.0 - from.0).to_radians() / 2.0;
+        let half_dlon = (to.1 - from.1).to_radians() / 2.0;
+        let a = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlon.sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
 
-        pub fn new(file_path: PathBuf) -> Self {
+    // Distinct addresses covered by ranges sorted by start
+    fn count_covered<'a>(ranges: impl Iterator<Item = &'a IpRange>) -> u64 {
+        let mut covered: u64 = 0;
+        let mut covered_until: Option<u32> = None;
 
-            let ip_ranges = match read_ip_ranges(file_path.to_str().expect("IP CSV file not found"), None) {
-                Ok(ranges) => ranges,
-                Err(e) => {
-                    log::error!("Error reading IP ranges: {}", e);
-                    Vec::new()
-                }
+        for range in ranges {
+            let start = match covered_until {
+                Some(until) if range.end <= until => continue,
+                Some(until) if range.start <= until => until + 1,
+                _ => range.start,
             };
-            Looker {
-                file_path,
-                ip_ranges,
-            }
-
+            covered += (range.end - start) as u64 + 1;
+            covered_until = Some(range.end);
         }
 
-        pub fn builder() -> LookerBuilder {
-            LookerBuilder::new()
-        }
+        covered
+    }
 
+    // Whitespace and quotes around a value, see LookerBuilder::trim_fields
+    fn trim_field(value: &str) -> &str {
+        value.trim_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'')
     }
 
-    impl LookerBuilder {
-        pub fn new() -> Self {
-            Self::default()
-        }
+    // Numeric columns are always required to be valid UTF-8
+    fn strict_field(record: &ByteRecord, index: usize, row: u64, column: &'static str) -> Result<String, LookerError> {
+        text_field(record, index, row, column, false).map(|value| value.into_owned())
+    }
 
-        pub fn file_path(mut self, path: PathBuf) -> Self {
-            self.file_path = Some(path);
-            self
+    // With lossy set, invalid UTF-8 is replaced with U+FFFD instead of failing the row
+    fn text_field<'a>(record: &'a ByteRecord, index: usize, row: u64, column: &'static str, lossy: bool) -> Result<Cow<'a, str>, LookerError> {
+        let bytes = record.get(index).ok_or(LookerError::WrongColumnCount { row, found: record.len(), expected: index + 1 })?;
+        if lossy {
+            return Ok(String::from_utf8_lossy(bytes));
         }
+        std::str::from_utf8(bytes).map(Cow::Borrowed).map_err(|_| LookerError::Parse {
+            row,
+            column,
+            value: String::from_utf8_lossy(bytes).into_owned(),
+            expected: "UTF-8",
+        })
+    }
 
-        pub fn allowed_countries(mut self, countries: Vec<String>) -> Self {
-            if countries.is_empty() {
-                log::warn!("Allowed countries is empty, filter will be ignored!");
-                self.allowed_countries = None;
-                return self;
-            }
-
-            self.allowed_countries = Some(countries);
-            self
+    fn parse_coordinate(value: &str, row: u64, column: &'static str) -> Result<Option<f64>, LookerError> {
+        if value.trim().is_empty() {
+            return Ok(None);
         }
+        parse_field(value, row, column, "a coordinate", |value| value.trim().parse().ok()).map(Some)
+    }
 
-        pub fn build(self) -> Result<Looker, Box<dyn Error>> {
-            let ip_ranges = match read_ip_ranges(self.file_path.as_ref().expect("IP CSV file not found").to_str().expect("Invalid file path"), self.allowed_countries.as_ref()) {
-                Ok(ranges) => ranges,
-                Err(e) => {
-                    log::error!("Error reading IP ranges: {}", e);
-                    Vec::new()
-                }
-            };
+    fn parse_flag(value: &str, row: u64, column: &'static str) -> Result<bool, LookerError> {
+        parse_field(value, row, column, "a boolean", |value| match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "t" | "y" => Some(true),
+            "0" | "false" | "no" | "f" | "n" | "" => Some(false),
+            _ => None,
+        })
+    }
 
-            Ok(Looker {
-                file_path: self.file_path.unwrap(),
-                ip_ranges,
-            })
-        }
+    fn parse_weight(value: &str, row: u64) -> Result<f32, LookerError> {
+        parse_field(value, row, "weight", "a non-negative weight", |value| {
+            value.trim().parse::<f32>().ok().filter(|weight| weight.is_finite() && *weight >= 0.0)
+        })
     }
 
-    fn read_ip_ranges(file_path: &str, allowed_countries: Option<&Vec<String>>) -> Result<Vec<IpRange>, Box<dyn Error>> {
-        let mut rdr = Reader::from_path(file_path)?;
-        let mut ip_ranges = Vec::new();
+    fn parse_field<T>(value: &str, row: u64, column: &'static str, expected: &'static str, parse: impl Fn(&str) -> Option<T>) -> Result<T, LookerError> {
+        parse(value).ok_or_else(|| LookerError::Parse {
+            row,
+            column,
+            value: value.to_string(),
+            expected,
+        })
+    }
 
-        let allowed_countries = match allowed_countries {
-            Some(filter) => {
-                if filter.is_empty() {
-                    log::warn!("Country filter is empty, filter will be ignored!");
-                    None
-                } else {
-                    Some(filter)
-                }
-            },
-            None => None,
+    // A copy of range limited to the inclusive interval [start, end], which it must overlap
+    fn clip_range(range: &IpRange, start: u32, end: u32) -> IpRange {
+        let mut clipped = range.clone();
+        clipped.start = clipped.start.max(start);
+        clipped.end = clipped.end.min(end);
+        clipped
+    }
+
+    // Parses a CIDR like 10.0.0.0/8 into its first and last address. A missing prefix length
+    // is read as /32 and host bits set in the address are ignored.
+    fn parse_cidr(cidr: &str) -> Result<(u32, u32), String> {
+        let cidr = cidr.trim();
+        let (address, prefix_len) = match cidr.split_once('/') {
+            Some((address, prefix_len)) => (address, prefix_len),
+            None => (cidr, "32"),
+        };
+        let address = ip_string_to_decimal(address).map_err(|e| format!("{} in '{}'", e, cidr))?;
+        let prefix_len: u32 = match prefix_len.parse() {
+            Ok(prefix_len) if prefix_len <= 32 => prefix_len,
+            _ => return Err(format!("Invalid prefix length in '{}'", cidr)),
         };
 
-        for result in rdr.records() {
-            let record = result?;
-            let start: u32 = record[0].parse()?;
-            let end: u32 = record[1].parse()?;
-            let country = record[2].to_string();
-            let region = record[4].to_string();
-            let city = record[5].to_string();
+        let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+        let start = address & mask;
+        Ok((start, start | !mask))
+    }
 
-            if let Some(ref filter) = allowed_countries {
-                if !filter.contains(&country) {
-                    continue;
-                }
-            }
-            
-            ip_ranges.push(IpRange { start, end, country, region, city });
+    // Parses a range like 1.0.0.0-1.0.0.255 into its first and last address
+    fn parse_dashed_range(range: &str) -> Result<(u32, u32), String> {
+        let (start, end) = range.split_once('-').ok_or_else(|| format!("Missing '-' in '{}'", range))?;
+        let start = ip_string_to_decimal(start.trim()).map_err(|e| format!("{} in '{}'", e, range))?;
+        let end = ip_string_to_decimal(end.trim()).map_err(|e| format!("{} in '{}'", e, range))?;
+        if start > end {
+            return Err(format!("Range '{}' ends before it starts", range));
         }
-
-        Ok(ip_ranges)
+        Ok((start, end))
     }
 
-    fn find_ip_range(ip: u32, ranges: &[IpRange]) -> Option<IpRange> {
-        ranges.binary_search_by(|range| {
-            if ip < range.start {
-                Ordering::Greater // Search the left side
-            } else if ip > range.end {
-                Ordering::Less // Search the right side
-            } else {
-                Ordering::Equal // IP is within this range
-            }
-        }).ok().map(|index| ranges[index].clone())
+    pub(crate) fn eq_ignore_case(a: &str, b: &str) -> bool {
+        a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase))
     }
 
     fn ip_string_to_decimal(ip: &str) -> Result<u32, String> {
-        let ip = Ipv4Addr::from_str(ip);
-        if ip.is_err() {
-            return Err("Invalid IP address".into());
+        match Ipv4Addr::from_str(ip) {
+            Ok(ip) => ip_to_decimal(&ip),
+            Err(_) => Err("Invalid IP address".into()),
         }
-        let ip = ip.unwrap();
-        ip_to_decimal(&ip)
     }
 
     fn ip_to_decimal(ip: &Ipv4Addr) -> Result<u32,String> {
-        let octets = ip.octets();
-        let decimal = (octets[0] as u32) << 24 
-            | (octets[1] as u32) << 16 
-            | (octets[2] as u32) << 8 
-            | octets[3] as u32;
-        Ok(decimal)
+        Ok(from_octets(ip.octets()))
     }
 
     pub fn look_up(ip: &str, file_path: &str) -> Option<IpRange> {
@@ -171,12 +3347,12 @@ pub mod ip_lookup {
                 ip_decimal
             }
         };
-         let ip_ranges_to_use = match read_ip_ranges(file_path, None) {
+         let ip_ranges_to_use = match read_ip_ranges(file_path, &LoadOptions::default()) {
             Err(e) => {
                 log::error!("Error: {}", e);
                 return None;
             },
-            Ok(ip_ranges) => {
+            Ok((ip_ranges, _)) => {
                 ip_ranges
             }
         };
@@ -193,7 +3369,12 @@ pub mod ip_lookup {
         }
     }
 
-    pub fn look_up_filtered(ip: &str, file_path: &str, allowed_countries: &Vec<String>) -> Option<IpRange> {
+    // LookerBuilder::for_each_range with the default options
+    pub fn for_each_range<E: From<LookerError>>(file_path: &str, f: impl FnMut(IpRange) -> Result<(), E>) -> Result<LoadStats, E> {
+        LookerBuilder::new().file_path(PathBuf::from(file_path)).for_each_range(f)
+    }
+
+    pub fn look_up_filtered(ip: &str, file_path: &str, allowed_countries: &[String]) -> Option<IpRange> {
         let ip_decimal_to_use = match ip_string_to_decimal(ip) {
             Err(e) => {
                 log::error!("Error: {}", e);
@@ -203,12 +3384,16 @@ pub mod ip_lookup {
                 ip_decimal
             }
         };
-         let ip_ranges_to_use = match read_ip_ranges(file_path, Some(allowed_countries)) {
+         let options = LoadOptions {
+            allowed_countries: Some(allowed_countries.to_vec()),
+            ..LoadOptions::default()
+        };
+         let ip_ranges_to_use = match read_ip_ranges(file_path, &options) {
             Err(e) => {
                 log::error!("Error: {}", e);
                 return None;
             },
-            Ok(ip_ranges) => {
+            Ok((ip_ranges, _)) => {
                 ip_ranges
             }
         };
@@ -225,7 +3410,7 @@ pub mod ip_lookup {
         }
     }
 
-    impl IpLookup for Looker {
+    impl IpLookup for RangeIndex {
 
         fn look_up(&self, ip: &str) -> Option<IpRange> {
             let ip = Ipv4Addr::from_str(ip);
@@ -252,9 +3437,7 @@ pub mod ip_lookup {
                     ip_decimal
                 }
             };
-            let ip_ranges_to_use = &self.ip_ranges;
-
-            match find_ip_range(ip_decimal_to_use, &ip_ranges_to_use[..]) {
+            match self.find_index(ip_decimal_to_use).map(|index| self.ip_ranges[index].clone()) {
                 Some(range) => {
                     log::trace!("IP is in range: {:?}", range);
                     Some(range)
@@ -268,6 +3451,646 @@ pub mod ip_lookup {
 
     }
 
+    impl IpLookup for LazyLooker {
+        fn look_up(&self, ip: &str) -> Option<IpRange> {
+            match Ipv4Addr::from_str(ip) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    None
+                },
+                Ok(ip) => self.look_up_ipv4(&ip)
+            }
+        }
+
+        fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+            let index = find_in_ranges(u32::from(*ip) as u64, &self.rows, |row| (row.start as u64, row.end as u64))?;
+            match self.range_at(index) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    None
+                },
+                Ok(range) => range
+            }
+        }
+    }
+
+    impl<S: RangeStore> IpLookup for Looker<S> {
+        fn look_up(&self, ip: &str) -> Option<IpRange> {
+            match Ipv4Addr::from_str(ip) {
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    None
+                },
+                Ok(ip) => self.look_up_ipv4(&ip)
+            }
+        }
+
+        fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+            match self.index.search(u32::from(*ip)).map(|index| self.index.get(index).clone()) {
+                Some(range) => {
+                    log::trace!("IP is in range: {:?}", range);
+                    Some(range)
+                },
+                None => {
+                    log::trace!("IP not found in any range");
+                    None
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn range(start: u32, end: u32, country: &str) -> IpRange {
+            IpRange::new(start, end, country.to_string(), String::new(), String::new())
+        }
+
+        fn fixture(name: &str) -> PathBuf {
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+        }
+
+        // A file of its own per test, so tests running in parallel don't share it
+        fn temp_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("ip-check-test-{}-{}", std::process::id(), name))
+        }
+
+        fn countries<'a>(ranges: impl IntoIterator<Item = &'a IpRange>) -> Vec<&'a str> {
+            ranges.into_iter().map(|range| range.country.as_str()).collect()
+        }
+
+        // A long range with a shorter one inside it and another overlapping its end
+        fn overlapping() -> RangeIndex {
+            vec![range(0, 100, "SE"), range(10, 20, "NO"), range(30, 200, "DK")].into_iter().collect()
+        }
+
+        #[test]
+        fn nearest_ranges_come_closest_first() {
+            let index: RangeIndex = vec![
+                range(0, 9, "SE").with_coordinates(59.3, 18.1),
+                range(10, 19, "NO").with_coordinates(59.9, 10.7),
+                range(20, 29, "XX"),
+                range(30, 39, "DK").with_coordinates(55.7, 12.6),
+                // Same place as the Swedish range, later in the table
+                range(40, 49, "FI").with_coordinates(59.3, 18.1),
+            ].into_iter().collect();
+
+            // Gothenburg
+            assert_eq!(countries(index.nearest_to(57.7, 12.0, 1)), ["DK"]);
+            assert_eq!(countries(index.nearest_to(57.7, 12.0, 2)), ["DK", "NO"]);
+            assert_eq!(countries(index.nearest_to(57.7, 12.0, 10)), ["DK", "NO", "SE", "FI"]);
+            assert_eq!(countries(index.nearest_to(59.3, 18.1, 1)), ["SE"]);
+            assert!(index.nearest_to(57.7, 12.0, 0).is_empty());
+        }
+
+        #[test]
+        fn health_fails_for_empty_and_overlapping_databases() {
+            let looker = Looker::try_new(fixture("locations.csv")).unwrap();
+            assert!(looker.health().is_ready(0.0));
+            assert!(!looker.health().is_ready(0.5));
+            assert!(!overlapping().health().is_ready(0.0));
+            assert!(!RangeIndex::default().health().is_ready(0.0));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn a_path_that_isnt_utf8_loads_nothing() {
+            use std::os::unix::ffi::OsStrExt;
+
+            let path = PathBuf::from(std::ffi::OsStr::from_bytes(b"locations-\xff.csv"));
+            assert!(Looker::new(path.clone()).is_empty());
+            assert!(matches!(Looker::try_new(path), Err(LookerError::InvalidFilePath(_))));
+        }
+
+        #[test]
+        fn excluding_countries_keeps_table_order_and_ignores_case() {
+            let index = overlapping();
+            assert_eq!(countries(index.ranges_excluding_countries(&[])), ["SE", "NO", "DK"]);
+            assert_eq!(countries(index.ranges_excluding_countries(&["no"])), ["SE", "DK"]);
+            assert_eq!(countries(index.ranges_excluding_countries(&["Se", "dk", "FI"])), ["NO"]);
+            assert!(index.ranges_excluding_countries(&["SE", "NO", "DK"]).is_empty());
+        }
+
+        #[test]
+        fn interval_finds_a_long_range_starting_before_shorter_ones() {
+            let index = overlapping();
+            assert_eq!(countries(index.look_up_interval(50, 60)), ["SE", "DK"]);
+            assert_eq!(countries(index.look_up_interval(15, 15)), ["SE", "NO"]);
+            assert_eq!(countries(index.look_up_interval(150, 300)), ["DK"]);
+            assert!(index.look_up_interval(201, 300).is_empty());
+            assert!(index.look_up_interval(60, 50).is_empty());
+
+            let clipped = index.look_up_interval_clipped(50, 60);
+            assert_eq!(clipped.iter().map(|range| (range.start(), range.end())).collect::<Vec<_>>(), [(50, 60), (50, 60)]);
+
+            let summary = index.summarize_block("0.0.0.48/28").unwrap();
+            assert_eq!(summary.locations.len(), 2);
+            assert_eq!(summary.unassigned, 0.0);
+        }
+
+        #[test]
+        fn best_match_prefers_city_rows_and_falls_back_to_the_country() {
+            let city = |start: u32, end: u32, name: &str| IpRange::new(start, end, "SE".to_string(), "Stockholm".to_string(), name.to_string());
+            // A country row with city rows inside it, two of them equally narrow and overlapping
+            let index: RangeIndex = vec![range(0, 65_535, "SE"), city(256, 511, "Stockholm"), city(1000, 1099, "Solna"), city(1050, 1149, "Sundbyberg")]
+                .into_iter().collect();
+            let ip = |decimal: u32| Ipv4Addr::from(decimal);
+
+            assert_eq!(index.look_up_all(&ip(300)).iter().map(|range| range.city.as_str()).collect::<Vec<_>>(), ["", "Stockholm"]);
+            assert_eq!(index.look_up_best(&ip(300)).unwrap().city, "Stockholm");
+
+            // Only the country row covers it
+            assert_eq!(index.look_up_all(&ip(5000)).len(), 1);
+            let fallback = index.look_up_best(&ip(5000)).unwrap();
+            assert_eq!((fallback.country.as_str(), fallback.city.as_str()), ("SE", ""));
+
+            // A tie between equally narrow rows goes to the first in the table
+            assert_eq!(index.look_up_all(&ip(1075)).len(), 3);
+            assert_eq!(index.look_up_best(&ip(1075)).unwrap().city, "Solna");
+            assert_eq!(index.look_up_best(&ip(1120)).unwrap().city, "Sundbyberg");
+
+            assert!(index.look_up_all(&ip(65_536)).is_empty());
+            assert!(index.look_up_best(&ip(65_536)).is_none());
+        }
+
+        #[test]
+        fn ranges_crossing_a_block_boundary_are_flagged() {
+            let ip = |a: u8, b: u8, c: u8, d: u8| u32::from(Ipv4Addr::new(a, b, c, d));
+            let index: RangeIndex = vec![
+                range(ip(9, 0, 0, 0), ip(9, 255, 255, 255), "US"),
+                range(ip(9, 255, 255, 0), ip(10, 0, 0, 255), "US"),
+                range(ip(11, 0, 0, 7), ip(11, 0, 0, 7), "SE"),
+                range(ip(11, 1, 0, 0), ip(11, 2, 255, 255), "NO"),
+            ].into_iter().collect();
+            let crossing = |prefix_len: u8| -> Vec<(u32, u32)> {
+                index.ranges_crossing_prefix(prefix_len).iter().map(|range| (range.start(), range.end())).collect()
+            };
+
+            assert_eq!(crossing(8), [(ip(9, 255, 255, 0), ip(10, 0, 0, 255))]);
+            assert_eq!(crossing(16), [(ip(9, 0, 0, 0), ip(9, 255, 255, 255)), (ip(9, 255, 255, 0), ip(10, 0, 0, 255)), (ip(11, 1, 0, 0), ip(11, 2, 255, 255))]);
+            // Everything is in the one /0 block, and only single addresses stay in their /32
+            assert!(crossing(0).is_empty());
+            assert_eq!(crossing(32).len(), 3);
+            assert_eq!(crossing(33), crossing(32));
+        }
+
+        #[test]
+        fn sorted_batches_match_one_lookup_per_ip() {
+            // Every other block of 100 addresses
+            let index: RangeIndex = (0..5000u32).map(|block| range(block * 200, block * 200 + 99, "SE")).collect();
+            let expected = |ips: &[Ipv4Addr]| -> Vec<Option<u32>> {
+                ips.iter().map(|ip| index.look_up_ipv4(ip).map(|range| range.start())).collect()
+            };
+            let batch = |ips: &[Ipv4Addr]| -> Vec<Option<u32>> {
+                index.look_up_sorted_batch(ips).iter().map(|range| range.map(IpRange::start)).collect()
+            };
+
+            let dense: Vec<Ipv4Addr> = (0..1_000_100u32).step_by(7).map(Ipv4Addr::from).collect();
+            assert_eq!(batch(&dense), expected(&dense));
+
+            // Far apart, with repeats, the first and last address and past the last range
+            let sparse: Vec<Ipv4Addr> = [0, 0, 99, 100, 150, 200, 199_999, 400_050, 400_050, 999_899, 999_900, u32::MAX].into_iter().map(Ipv4Addr::from).collect();
+            assert_eq!(batch(&sparse), expected(&sparse));
+            assert!(batch(&[]).is_empty());
+
+            // Out of order, an IP is only found in the range the cursor is on
+            let unsorted: Vec<Ipv4Addr> = [50_010, 50_020, 10].into_iter().map(Ipv4Addr::from).collect();
+            assert_eq!(batch(&unsorted), [Some(50_000), Some(50_000), None]);
+        }
+
+        #[test]
+        fn a_subnet_covered_by_overlapping_countries_is_not_homogeneous() {
+            let index = overlapping();
+            // SE and DK both cover 0.0.0.48 to 0.0.0.63
+            assert_eq!(index.subnet_is_homogeneous("0.0.0.48/28", FieldSet::CountryOnly), Some(false));
+            assert_eq!(index.subnet_is_homogeneous("0.0.0.48/28", FieldSet::All), Some(false));
+            assert_eq!(index.subnet_is_homogeneous("0.0.0.128/26", FieldSet::CountryOnly), Some(true));
+            assert_eq!(index.subnet_is_homogeneous("0.0.1.0/24", FieldSet::CountryOnly), None);
+            assert_eq!(index.subnet_is_homogeneous("not a cidr", FieldSet::CountryOnly), None);
+        }
+
+        #[test]
+        fn distinct_cidr_matches_include_overlapping_ranges_once() {
+            let index = overlapping();
+            let (ranges, errors) = index.look_up_cidrs_distinct(&["0.0.0.48/28", "0.0.0.50/32", "0.0.0.16/30", "not a cidr"]);
+            assert_eq!(countries(ranges), ["SE", "NO", "DK"]);
+            assert!(matches!(&errors[..], [LookupError::InvalidIp(cidr)] if cidr == "not a cidr"));
+
+            let (ranges, errors) = index.look_up_cidrs_distinct(&["0.0.0.48/28"]);
+            assert_eq!(countries(ranges), ["SE", "DK"]);
+            assert!(errors.is_empty());
+        }
+
+        #[test]
+        fn optional_columns_only_allocate_when_mapped() {
+            let plain = Looker::try_new(fixture("locations.csv")).unwrap();
+            assert!(plain.ip_ranges.iter().all(|range| range.extras.is_none()));
+            assert!(std::mem::size_of::<IpRange>() <= 128);
+
+            let named = LookerBuilder::new().file_path(fixture("locations.csv")).country_name_column(3).try_build().unwrap();
+            let australia = named.find(&Ipv4Addr::new(1, 0, 0, 1)).unwrap();
+            assert_eq!(australia.country_name(), Some("Australia"));
+            assert_eq!(australia.org(), None);
+            assert!(australia.coordinates().is_none());
+            assert!(australia.raw().is_none());
+            let raw = LookerBuilder::new().file_path(fixture("locations.csv")).keep_raw(true).try_build().unwrap();
+            assert_eq!(&raw.find(&Ipv4Addr::new(1, 0, 0, 1)).unwrap().raw().unwrap()[3], "Australia");
+            assert_eq!(australia.clone().with_org("APNIC").org(), Some("APNIC"));
+
+            let weighted = range(0, 9, "SE").with_candidates(vec![(Location { country: "NO".into(), ..Location::default() }, 0.7)]);
+            assert_eq!(weighted.country, "NO");
+            assert_eq!(weighted.candidates().len(), 1);
+            assert!(range(0, 9, "SE").candidates().is_empty());
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn ndjson_ranges_only_allocate_extras_when_given() {
+            let lines = "{\"start\":0,\"end\":9,\"country\":\"SE\"}\n{\"start\":10,\"end\":19,\"country\":\"NO\",\"org\":\"Telenor\",\"latitude\":59.9,\"longitude\":10.7}\n";
+            let looker = Looker::from_ndjson(lines.as_bytes()).unwrap();
+            assert!(looker.ip_ranges[0].extras.is_none());
+            assert_eq!(looker.ip_ranges[1].org(), Some("Telenor"));
+            assert_eq!(looker.ip_ranges[1].coordinates(), Some((59.9, 10.7)));
+        }
+
+        #[test]
+        fn symbols_are_interned_in_place() {
+            let city = |start: u32, country: &str, name: &str| IpRange::new(start, start + 9, country.to_string(), String::new(), name.to_string());
+            let index: RangeIndex = vec![city(0, "SE", "Lund"), city(10, "NO", "Oslo"), city(20, "SE", "Malmö")].into_iter().collect();
+            let before = index.ip_ranges.as_ptr();
+            let index = index.with_symbols();
+            // Not shared with anything, so the ranges weren't copied
+            assert_eq!(index.ip_ranges.as_ptr(), before);
+
+            let ids: Vec<u32> = index.ip_ranges.iter().map(|range| range.country_id().unwrap()).collect();
+            assert_eq!(ids[0], ids[2]);
+            assert_ne!(ids[0], ids[1]);
+            for range in index.ip_ranges.iter() {
+                assert_eq!(index.resolve_symbol(range.country_id().unwrap()), range.country);
+                assert_eq!(index.resolve_symbol(range.region_id().unwrap()), range.region);
+                assert_eq!(index.resolve_symbol(range.city_id().unwrap()), range.city);
+            }
+
+            // Extending a clone copies its ranges and interns them again, the snapshot keeps the ids
+            let snapshot = index.snapshot();
+            let mut extended = index.clone();
+            extended.extend([city(30, "DK", "Lund")]);
+            assert!(snapshot.iter().all(|range| range.country_id().is_some()));
+            assert_eq!(extended.ip_ranges[3].city_id(), extended.ip_ranges[0].city_id());
+            assert_eq!(index.len(), 3);
+        }
+
+        #[test]
+        fn prefix_windows_start_at_long_ranges_reaching_into_the_block() {
+            let index: RangeIndex = vec![range(0, 5, "AU"), range(1, 0x3_0000, "SE"), range(10, 20, "NO"), range(0x2_0000, 0x2_0010, "DK")]
+                .into_iter().collect::<RangeIndex>()
+                .with_prefix_index();
+            let windows = &index.prefix_index.as_ref().unwrap().windows;
+            assert_eq!(windows[0], (0, 3));
+            assert_eq!(windows[2], (1, 4));
+            assert_eq!(windows[3], (1, 4));
+            assert_eq!(windows[4], (4, 4));
+        }
+
+        #[test]
+        fn derived_lookers_dont_reload_the_whole_file() {
+            let looker = Looker::try_new(fixture("locations.csv")).unwrap();
+            let (mut lower, _) = looker.clone().split_at_ip(&Ipv4Addr::new(1, 0, 4, 0));
+            assert_eq!(lower.len(), 3);
+            assert!(matches!(lower.reload_if_modified(), Err(LookerError::MissingFilePath)));
+            assert!(matches!(lower.reload(), Err(LookerError::MissingFilePath)));
+            assert_eq!(lower.len(), 3);
+
+            for mut derived in [looker.merge(&lower), looker.collapse_to_country()] {
+                assert!(matches!(derived.reload_if_modified(), Err(LookerError::MissingFilePath)));
+            }
+        }
+
+        #[test]
+        fn binary_databases_reload_as_binary() {
+            let path = temp_path("reload.bin");
+            let write = |csv: &str| LookerBuilder::new().file_path(fixture(csv)).write_binary(std::fs::File::create(&path).unwrap()).unwrap();
+
+            write("locations.csv");
+            let mut looker = Looker::from_binary(path.clone()).unwrap();
+            assert_eq!(looker.len(), 7);
+            assert!(!looker.reload_if_modified().unwrap());
+            looker.reload().unwrap();
+            assert_eq!(looker.len(), 7);
+            assert_eq!(looker.look_up("1.0.4.1").unwrap().city, "Melbourne");
+
+            // The header row of headerless.csv is its first range, so one fewer range
+            write("headerless.csv");
+            assert!(looker.reload_if_modified().unwrap());
+            assert_eq!(looker.len(), 6);
+
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn network_order_dumps_reload_in_network_order() {
+            let path = temp_path("reload.be");
+            let write = |ranges: &[(u32, u32, &str)]| {
+                let mut bytes = Vec::new();
+                for (start, end, country) in ranges {
+                    bytes.extend(start.to_be_bytes());
+                    bytes.extend(end.to_be_bytes());
+                    for text in [*country, "", ""] {
+                        bytes.extend((text.len() as u16).to_be_bytes());
+                        bytes.extend(text.as_bytes());
+                    }
+                }
+                std::fs::write(&path, bytes).unwrap();
+            };
+
+            write(&[(0, 255, "SE")]);
+            let mut looker = Looker::from_network_order_binary(path.clone()).unwrap();
+            assert_eq!(looker.len(), 1);
+            assert!(!looker.reload_if_modified().unwrap());
+
+            write(&[(0, 255, "SE"), (256, 511, "NO")]);
+            assert!(looker.reload_if_modified().unwrap());
+            assert_eq!(countries(looker.ip_ranges.iter()), ["SE", "NO"]);
+
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn streamed_ranges_never_end_before_they_start() {
+            let path = temp_path("reversed_stream.csv");
+            std::fs::write(&path, "start,end,country,cname,region,city\n100,50,SE,Sweden,,\n200,299,NO,Norway,,\n").unwrap();
+
+            let mut streamed = Vec::new();
+            let stats = for_each_range(path.to_str().unwrap(), |range| {
+                streamed.push((range.start(), range.address_count()));
+                Ok::<_, LookerError>(())
+            }).unwrap();
+            assert_eq!(streamed, [(200, 100)]);
+            assert_eq!(stats.reversed, 1);
+            assert_eq!(stats.rows_kept, 1);
+
+            let looker = Looker::try_new(path.clone()).unwrap();
+            assert_eq!(looker.load_stats().reversed, 1);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn fixed_width_databases_reload_with_their_schema() {
+            let path = temp_path("reload.txt");
+            let schema = FixedWidthSchema { start: 0..10, end: 10..20, country: 20..22, region: 22..30, city: 30..38 };
+            let write = |rows: &[(u32, u32, &str)]| {
+                let lines: String = rows.iter().map(|(start, end, country)| format!("{:<10}{:<10}{:<2}{:<8}{:<8}\n", start, end, country, "", "")).collect();
+                std::fs::write(&path, lines).unwrap();
+            };
+
+            write(&[(0, 255, "SE")]);
+            let mut looker = Looker::from_fixed_width(path.clone(), &schema).unwrap();
+            assert_eq!(looker.len(), 1);
+            assert!(!looker.reload_if_modified().unwrap());
+
+            write(&[(0, 255, "SE"), (256, 511, "NO")]);
+            assert!(looker.reload_if_modified().unwrap());
+            assert_eq!(countries(looker.ip_ranges.iter()), ["SE", "NO"]);
+            looker.reload().unwrap();
+            assert_eq!(looker.len(), 2);
+
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[cfg(feature = "zip")]
+        #[test]
+        fn zip_entries_claiming_a_huge_size_are_not_trusted() {
+            use std::io::Write;
+
+            let path = temp_path("huge_claim.zip");
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+            writer.start_file("locations.csv", zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(&std::fs::read(fixture("locations.csv")).unwrap()).unwrap();
+            writer.finish().unwrap();
+            assert_eq!(Looker::from_zip(&path, None).unwrap().len(), 7);
+
+            // Claim an uncompressed size of almost 4 GiB in both headers
+            let mut bytes = std::fs::read(&path).unwrap();
+            for (signature, offset) in [(b"PK\x03\x04", 22), (b"PK\x01\x02", 24)] {
+                let header = bytes.windows(4).position(|window| window == signature).unwrap();
+                bytes[header + offset..header + offset + 4].copy_from_slice(&u32::MAX.to_le_bytes()[..]);
+                bytes[header + offset] = 0xfe;
+            }
+            std::fs::write(&path, bytes).unwrap();
+            // Reading the entry may fail on the wrong size, but it must not reserve for it first
+            let _ = Looker::from_zip(&path, None);
+
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn comment_lines_and_blank_lines_are_skipped() {
+            let expected = LoadStats { rows_read: 4, rows_kept: 4, ..LoadStats::default() };
+            for (name, delimiter) in [("comments.csv", b','), ("comments_semicolon.csv", b';')] {
+                let looker = LookerBuilder::new().file_path(fixture(name)).comment(Some(b'#')).delimiter(delimiter).try_build().unwrap();
+                assert_eq!(looker.len(), 4, "{}", name);
+                assert_eq!(looker.load_stats(), expected, "{}", name);
+                assert_eq!(countries(looker.ip_ranges.iter()), ["ZZ", "AU", "CN", "US"]);
+                // Commented out, not loaded
+                assert!(looker.look_up("1.0.4.1").is_none());
+            }
+
+            // Without the option a comment line is read as a row and fails the load
+            assert!(LookerBuilder::new().file_path(fixture("comments.csv")).try_build().is_err());
+        }
+
+        #[test]
+        fn shards_of_a_headerless_file_stay_as_they_were_cut() {
+            let looker = LookerBuilder::new().file_path(fixture("headerless.csv")).has_headers(false).try_build().unwrap();
+            let total: u64 = looker.ip_ranges.iter().map(IpRange::address_count).sum();
+            let mut shards = looker.shard(3);
+            assert_eq!(shards.len(), 3);
+
+            let before: Vec<Vec<(u32, u32)>> = shards.iter()
+                .map(|shard| shard.ip_ranges.iter().map(|range| (range.start, range.end)).collect())
+                .collect();
+            for shard in &mut shards {
+                assert!(matches!(shard.reload_if_modified(), Err(LookerError::MissingFilePath)));
+            }
+            let after: Vec<Vec<(u32, u32)>> = shards.iter()
+                .map(|shard| shard.ip_ranges.iter().map(|range| (range.start, range.end)).collect())
+                .collect();
+            assert_eq!(before, after);
+
+            // Each shard starts past the end of the one before, and together they hold every address
+            let flat: Vec<(u32, u32)> = after.concat();
+            assert!(flat.windows(2).all(|pair| pair[0].1 < pair[1].0));
+            assert_eq!(flat.iter().map(|(start, end)| (end - start) as u64 + 1).sum::<u64>(), total);
+            assert_eq!(shards[0].look_up("0.0.0.1").unwrap().country, "ZZ");
+        }
+
+        #[test]
+        fn the_first_row_is_a_header_unless_told_otherwise() {
+            let skipped = LookerBuilder::new().file_path(fixture("headerless.csv")).try_build().unwrap();
+            assert_eq!(skipped.len(), 6);
+            assert!(skipped.look_up("0.0.0.1").is_none());
+
+            let kept = LookerBuilder::new().file_path(fixture("headerless.csv")).has_headers(false).try_build().unwrap();
+            assert_eq!(kept.len(), 7);
+            assert_eq!(kept.look_up("0.0.0.1").unwrap().country, "ZZ");
+
+            let with_header = LookerBuilder::new().file_path(fixture("locations.csv")).try_build().unwrap();
+            assert_eq!(with_header.len(), 7);
+        }
+
+        #[cfg(feature = "rayon")]
+        #[test]
+        fn parallel_batches_keep_the_input_order() {
+            // Every other block of 1000 addresses, each with its own city
+            let index: RangeIndex = (0..10_000u32)
+                .map(|block| IpRange::new(block * 2000, block * 2000 + 999, "SE".to_string(), String::new(), block.to_string()))
+                .collect();
+
+            // Shuffled hits and misses, well over what one rayon task handles
+            let mut state: u32 = 0x2545_f491;
+            let ips: Vec<Ipv4Addr> = (0..200_000).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                Ipv4Addr::from(state % 20_000_000)
+            }).collect();
+
+            let key = |results: Vec<Option<IpRange>>| -> Vec<Option<String>> {
+                results.into_iter().map(|range| range.map(|range| range.city)).collect()
+            };
+            let parallel = key(index.par_look_up_many(&ips));
+            assert_eq!(parallel.len(), ips.len());
+            assert!(parallel.iter().any(Option::is_some) && parallel.iter().any(Option::is_none));
+            assert_eq!(parallel, key(index.look_up_many(&ips)));
+            for (ip, city) in ips.iter().zip(&parallel) {
+                let expected = (u32::from(*ip) % 2000 < 1000).then(|| (u32::from(*ip) / 2000).to_string());
+                assert_eq!(city, &expected);
+            }
+        }
+
+        fn malformed_fixtures() -> Vec<PathBuf> {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(fixture("malformed")).unwrap().map(|entry| entry.unwrap().path()).collect();
+            paths.push(fixture("huge_count.csv"));
+            paths.sort();
+            paths
+        }
+
+        // None of these may panic, whatever the file holds
+        #[test]
+        fn malformed_files_never_panic() {
+            let schema = FixedWidthSchema { start: 0..10, end: 10..20, country: 20..22, region: 22..30, city: 30..40 };
+            let builders: [fn(LookerBuilder) -> LookerBuilder; 8] = [
+                |builder| builder,
+                |builder| builder.end_bound(EndBound::Exclusive),
+                |builder| builder.length_column(1),
+                |builder| builder.lossy_utf8(true),
+                |builder| builder.trim_fields(true).normalize_countries(true),
+                |builder| builder.has_headers(false),
+                |builder| builder.country_name_column(3).region_code_column(4).anycast_column(5),
+                |builder| builder.max_rows(0).require_city(true),
+            ];
+
+            for path in malformed_fixtures() {
+                let path_str = path.to_str().unwrap();
+                let _ = Looker::new(path.clone());
+                let _ = Looker::try_new(path.clone());
+                for configure in builders {
+                    let _ = configure(LookerBuilder::new().file_path(path.clone())).build();
+                    let _ = configure(LookerBuilder::new().file_path(path.clone())).try_build();
+                    let _ = configure(LookerBuilder::new().file_path(path.clone())).for_each_range(|_| Ok::<(), LookerError>(()));
+                }
+                let _ = look_up("1.0.0.1", path_str);
+                let _ = look_up_filtered("1.0.0.1", path_str, &["AU".to_string()]);
+                let _ = Looker::from_fixed_width(path.clone(), &schema);
+                if let Ok(lazy) = LazyLooker::open(path.clone()) {
+                    let _ = lazy.look_up("1.0.0.1");
+                }
+
+                let mut looker = Looker::try_new(fixture("locations.csv")).unwrap();
+                looker.file_path = path.clone();
+                if looker.reload().is_err() {
+                    assert_eq!(looker.len(), 7, "a failed reload from {} kept the old ranges", path_str);
+                }
+            }
+        }
+
+        #[test]
+        fn malformed_rows_are_reported_as_errors() {
+            let load = |name: &str, configure: fn(LookerBuilder) -> LookerBuilder| configure(LookerBuilder::new().file_path(fixture("malformed").join(name))).try_build();
+
+            assert!(matches!(load("short_row.csv", |builder| builder), Err(LookerError::WrongColumnCount { row: 2, found: 3, expected: 6 })));
+            assert!(matches!(load("non_numeric.csv", |builder| builder), Err(LookerError::Parse { row: 2, column: "start", .. })));
+            assert!(matches!(load("negative.csv", |builder| builder), Err(LookerError::Parse { row: 2, column: "start", .. })));
+            assert!(matches!(load("end_too_large.csv", |builder| builder), Err(LookerError::Parse { row: 2, column: "end", .. })));
+            assert!(matches!(load("exclusive_end_zero.csv", |builder| builder.end_bound(EndBound::Exclusive)), Err(LookerError::Parse { row: 2, column: "end", .. })));
+            assert!(matches!(load("bad_utf8_start.csv", |builder| builder.lossy_utf8(true)), Err(LookerError::Parse { row: 2, column: "start", .. })));
+            assert!(load("unterminated_quote.csv", |builder| builder).is_err());
+            assert!(load("binary.csv", |builder| builder).is_err());
+
+            assert!(load("bad_utf8.csv", |builder| builder).is_err());
+            let lossy = load("bad_utf8.csv", |builder| builder.lossy_utf8(true)).unwrap();
+            assert_eq!(lossy.look_up("1.0.0.1").unwrap().city, "Bris\u{FFFD}\u{FFFD}bane");
+
+            assert!(matches!(Looker::try_new(fixture("malformed/empty.csv")), Err(LookerError::EmptyDatabase(_))));
+            assert!(matches!(Looker::try_new(fixture("malformed/header_only.csv")), Err(LookerError::EmptyDatabase(_))));
+            assert!(Looker::try_new(fixture("malformed/missing.csv")).is_err());
+        }
+
+        #[test]
+        fn a_count_too_large_for_u64_fails_the_row() {
+            let looker = LookerBuilder::new().file_path(fixture("huge_count.csv")).length_column(1).try_build();
+            assert!(matches!(looker, Err(LookerError::Parse { row: 2, column: "count", .. })));
+        }
+
+        #[test]
+        fn ranges_at_the_edges_of_the_address_space_match_both_ends() {
+            let whole = vec![range(0, u32::MAX, "SE")];
+            assert_eq!(find_ip_range_index(0, &whole), Some(0));
+            assert_eq!(find_ip_range_index(u32::MAX, &whole), Some(0));
+            assert_eq!(find_ip_range_index(1 << 31, &whole), Some(0));
+
+            let edges = vec![range(0, 0, "ZZ"), range(1, u32::MAX - 1, "SE"), range(u32::MAX, u32::MAX, "ZZ")];
+            assert_eq!(find_ip_range_index(0, &edges), Some(0));
+            assert_eq!(find_ip_range_index(1, &edges), Some(1));
+            assert_eq!(find_ip_range_index(u32::MAX - 1, &edges), Some(1));
+            assert_eq!(find_ip_range_index(u32::MAX, &edges), Some(2));
+
+            let inside = vec![range(1, u32::MAX - 1, "SE")];
+            assert_eq!(find_ip_range_index(0, &inside), None);
+            assert_eq!(find_ip_range_index(u32::MAX, &inside), None);
+
+            let index: RangeIndex = whole.into_iter().collect();
+            assert!(index.look_up_ipv4(&Ipv4Addr::new(0, 0, 0, 0)).is_some());
+            assert!(index.look_up_ipv4(&Ipv4Addr::new(255, 255, 255, 255)).is_some());
+        }
+    }
 }
 
-pub use crate::ip_lookup::{look_up, look_up_filtered, Looker, LookerBuilder, IpLookup, };
+pub mod countries;
+pub mod classify;
+mod binary;
+pub mod convert;
+pub mod ffi;
+pub mod query;
+#[cfg(feature = "prometheus")]
+mod metrics;
+#[cfg(feature = "shared")]
+pub mod shared;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "debug-tools")]
+pub mod recording;
+
+pub use crate::ip_lookup::{look_up, look_up_filtered, for_each_range, find_in_ranges, Looker, LazyLooker, LookerBuilder, RangeStore, LookerError, LoadStats, LookupError, Resolution, LocationMatch, BudgetExceeded, BlockSummary, IpLookup, IpRange, IpRangeBuf, Location, RangeIndex, IpFamily, Precision, FieldSet, EndBound, ReportFormat, FixedWidthSchema, Health, SortedRanges, DatabaseDiff, CoverageDiff, };
+pub use crate::countries::normalize_country;
+pub use crate::classify::{classify, IpClass};
+pub use crate::ffi::CIpRange;
+pub use crate::query::Query;
+pub use crate::convert::{is_valid_ipv4, parse_ipv4, from_octets};
+#[cfg(feature = "shared")]
+pub use crate::shared::SharedLooker;
+#[cfg(feature = "testing")]
+pub use crate::testing::generate_test_ranges;
+#[cfg(feature = "debug-tools")]
+pub use crate::recording::RecordingLooker;
@@ -0,0 +1,24 @@
+use std::collections::HashSet;
+
+use crate::ip_lookup::RangeIndex;
+
+impl RangeIndex {
+    // Gauges for the loaded database in the Prometheus text exposition format, ready to be
+    // returned from a /metrics handler next to other metrics
+    pub fn prometheus_metrics(&self) -> String {
+        let health = self.health();
+        let countries = self.ip_ranges.iter().map(|range| range.country.as_str()).collect::<HashSet<_>>().len();
+
+        let mut metrics = String::new();
+        let gauges = [
+            ("ip_ranges_total", "Number of IP ranges loaded", health.row_count.to_string()),
+            ("ip_countries_total", "Number of distinct countries in the loaded ranges", countries.to_string()),
+            ("ip_coverage_addresses", "Number of IPv4 addresses covered by at least one range", health.covered_addresses.to_string()),
+            ("ip_coverage_ratio", "Share of the IPv4 address space covered by at least one range", health.coverage.to_string()),
+        ];
+        for (name, help, value) in gauges {
+            metrics.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value));
+        }
+        metrics
+    }
+}
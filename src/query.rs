@@ -0,0 +1,60 @@
+use std::cell::OnceCell;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+use crate::ip_lookup::{IpRange, RangeIndex, UNKNOWN_COUNTRY};
+
+// A lookup that runs the first time one of its getters is called and is then reused.
+// An IP that doesn't parse is logged and behaves like a miss.
+#[derive(Debug)]
+pub struct Query<'a> {
+    index: &'a RangeIndex,
+    ip: Option<Ipv4Addr>,
+    found: OnceCell<Option<&'a IpRange>>,
+}
+
+impl RangeIndex {
+    pub fn query(&self, ip: &str) -> Query<'_> {
+        let ip = match Ipv4Addr::from_str(ip.trim()) {
+            Err(e) => {
+                log::error!("Error: {}", e);
+                None
+            },
+            Ok(ip) => Some(ip)
+        };
+        Query { index: self, ip, found: OnceCell::new() }
+    }
+}
+
+impl<'a> Query<'a> {
+    pub fn range(&self) -> Option<&'a IpRange> {
+        *self.found.get_or_init(|| self.ip.and_then(|ip| self.index.look_up_u32(u32::from(ip))))
+    }
+
+    pub fn is_found(&self) -> bool {
+        self.range().is_some()
+    }
+
+    pub fn country(&self) -> Option<&'a str> {
+        self.range().map(|range| range.country.as_str())
+    }
+
+    pub fn region(&self) -> Option<&'a str> {
+        self.range().map(|range| range.region.as_str())
+    }
+
+    pub fn city(&self) -> Option<&'a str> {
+        self.range().map(|range| range.city.as_str())
+    }
+
+    // The country, or "unknown" on a miss
+    pub fn or_unknown(&self) -> &'a str {
+        self.country().unwrap_or(UNKNOWN_COUNTRY)
+    }
+}
+
+impl From<Query<'_>> for Option<IpRange> {
+    fn from(query: Query<'_>) -> Self {
+        query.range().cloned()
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::ip_lookup::{IpLookup, IpRange};
+
+// Wraps any IpLookup and remembers the last capacity lookups with their results, for
+// replaying the queries that led to a surprising answer. Safe to share between threads,
+// every lookup briefly locks the history.
+#[derive(Debug)]
+pub struct RecordingLooker<L: IpLookup> {
+    inner: L,
+    capacity: usize,
+    history: Mutex<VecDeque<(Ipv4Addr, Option<IpRange>)>>,
+}
+
+impl<L: IpLookup> RecordingLooker<L> {
+    pub fn new(inner: L, capacity: usize) -> Self {
+        RecordingLooker {
+            inner,
+            capacity,
+            history: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    // The recorded lookups, oldest first. Strings that weren't an IP aren't recorded.
+    pub fn history(&self) -> Vec<(Ipv4Addr, Option<IpRange>)> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    pub fn clear_history(&self) {
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L: IpLookup> IpLookup for RecordingLooker<L> {
+    fn look_up(&self, ip: &str) -> Option<IpRange> {
+        match Ipv4Addr::from_str(ip) {
+            Err(e) => {
+                log::error!("Error: {}", e);
+                None
+            },
+            Ok(ip) => self.look_up_ipv4(&ip)
+        }
+    }
+
+    fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+        let result = self.inner.look_up_ipv4(ip);
+        if self.capacity > 0 {
+            let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+            history.push_back((*ip, result.clone()));
+        }
+        result
+    }
+}
@@ -0,0 +1,139 @@
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwap;
+
+use crate::ip_lookup::{IpLookup, IpRange, Looker};
+
+// A Looker that can be replaced while other threads are reading from it.
+// Lookups always see either the old or the new database, never a mix of both.
+#[derive(Debug)]
+pub struct SharedLooker {
+    current: ArcSwap<Looker>,
+    // Shortest time between two reloads from look_up_or_reload
+    min_reload_interval: Duration,
+    last_reload: Mutex<Option<Instant>>,
+}
+
+impl SharedLooker {
+    pub fn new(looker: Looker) -> Self {
+        SharedLooker {
+            current: ArcSwap::from_pointee(looker),
+            min_reload_interval: Duration::from_secs(1),
+            last_reload: Mutex::new(None),
+        }
+    }
+
+    // See look_up_or_reload, one second by default
+    pub fn with_min_reload_interval(mut self, interval: Duration) -> Self {
+        self.min_reload_interval = interval;
+        self
+    }
+
+    pub fn lookup(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+        self.current.load().look_up_ipv4(ip)
+    }
+
+    // Like lookup, but a miss reloads the file if it changed (see Looker::reload_if_modified)
+    // and retries once, for IPs that are about to be added to a database being updated.
+    // Misses within min_reload_interval of the last reload, or while another thread is
+    // reloading, don't reload and return None right away, so a burst of misses costs at most
+    // one reload. A failed reload is logged and keeps the current database.
+    pub fn look_up_or_reload(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+        if let Some(range) = self.lookup(ip) {
+            return Some(range);
+        }
+
+        let mut last_reload = match self.last_reload.try_lock() {
+            Ok(last_reload) => last_reload,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        if last_reload.is_some_and(|last| last.elapsed() < self.min_reload_interval) {
+            return None;
+        }
+        *last_reload = Some(Instant::now());
+
+        // Cheap, the clone shares the ranges until the reload replaces them
+        let mut looker = (*self.current.load_full()).clone();
+        match looker.reload_if_modified() {
+            Err(e) => {
+                log::error!("Error: {}", e);
+                None
+            },
+            Ok(false) => None,
+            Ok(true) => {
+                self.swap(looker);
+                self.lookup(ip)
+            }
+        }
+    }
+
+    // Installs a freshly loaded database, lookups already in progress finish on the old one
+    pub fn swap(&self, new: Looker) {
+        self.current.store(Arc::new(new));
+    }
+
+    pub fn snapshot(&self) -> Arc<Looker> {
+        self.current.load_full()
+    }
+}
+
+impl From<Looker> for SharedLooker {
+    fn from(looker: Looker) -> Self {
+        SharedLooker::new(looker)
+    }
+}
+
+impl IpLookup for SharedLooker {
+    fn look_up(&self, ip: &str) -> Option<IpRange> {
+        self.current.load().look_up(ip)
+    }
+
+    fn look_up_ipv4(&self, ip: &Ipv4Addr) -> Option<IpRange> {
+        self.lookup(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn database(country: &str) -> Looker {
+        (0..100u32).map(|block| IpRange::new(block * 1000, block * 1000 + 999, country.to_string(), String::new(), String::new())).collect()
+    }
+
+    #[test]
+    fn lookups_during_swaps_see_one_whole_database() {
+        let shared = SharedLooker::new(database("SE"));
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            for reader in 0..4u32 {
+                let (shared, done) = (&shared, &done);
+                scope.spawn(move || {
+                    let mut ip = reader;
+                    while !done.load(Ordering::Relaxed) {
+                        ip = (ip + 7919) % 100_000;
+                        let country = shared.lookup(&Ipv4Addr::from(ip)).expect("every address is covered").country;
+                        assert!(country == "SE" || country == "NO", "unexpected country {}", country);
+
+                        // Every range of one snapshot belongs to the same database
+                        let snapshot = shared.snapshot();
+                        let first = snapshot.look_up_ipv4(&Ipv4Addr::from(0)).unwrap().country;
+                        let last = snapshot.look_up_ipv4(&Ipv4Addr::from(99_999)).unwrap().country;
+                        assert_eq!(first, last);
+                    }
+                });
+            }
+
+            for swap in 0..500 {
+                shared.swap(database(if swap % 2 == 0 { "NO" } else { "SE" }));
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(shared.lookup(&Ipv4Addr::from(12_345)).unwrap().country, "SE");
+    }
+}
@@ -0,0 +1,52 @@
+use crate::countries::COUNTRIES;
+use crate::ip_lookup::IpRange;
+
+const REGIONS: &[&str] = &["North", "South", "East", "West", "Central", "Coastal", "Highlands", "Lakes"];
+
+const CITY_PARTS: &[&str] = &["Ash", "Brook", "Dale", "Fair", "Glen", "Haven", "Mill", "Port", "Ridge", "Stone", "Vale", "Wood"];
+
+// Small xorshift generator so the same seed always gives the same database on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+// count sorted, non-overlapping ranges spread over the IPv4 space with random locations,
+// safe to hand to Looker::from_ranges or SortedRanges::new. The space is split into count
+// equal slots and each range lies somewhere inside its own slot. At most 2^32 ranges.
+pub fn generate_test_ranges(count: usize, seed: u64) -> Vec<IpRange> {
+    let count = (count as u64).min(1 << 32);
+    let mut rng = Rng::new(seed);
+    let mut ranges = Vec::with_capacity(count as usize);
+    if count == 0 {
+        return ranges;
+    }
+    let slot = (1u64 << 32) / count;
+
+    for index in 0..count {
+        let slot_start = index * slot;
+        let start = slot_start + rng.next() % slot.div_ceil(2);
+        let end = start + rng.next() % (slot_start + slot - start);
+
+        let (country, _, _) = COUNTRIES[(rng.next() % COUNTRIES.len() as u64) as usize];
+        let city = format!("{}{}", rng.pick(CITY_PARTS), rng.pick(CITY_PARTS).to_lowercase());
+        ranges.push(IpRange::new(start as u32, end as u32, country.to_string(), rng.pick(REGIONS).to_string(), city));
+    }
+
+    ranges
+}